@@ -1,20 +1,28 @@
 use std::error::Error;
 use std::sync::Arc;
 use lazy_static::lazy_static;
-use log::error;
+use log::{error, warn};
 use regex::Regex;
-use reqwest::Client;
+use reqwest::{Client, StatusCode, Url};
 use reqwest_cookie_store::CookieStoreMutex;
 use scraper::{Html, Selector};
+use tokio::time::{sleep, Duration};
 use crate::auth::cookies::load_cookie_store;
+use crate::auth::fixtures::{is_replaying, record, replay};
+use crate::auth::limiter::acquire_request_slot;
+use crate::auth::retry::{backoff_delay, is_transient_error, is_transient_status, retry_after, RequestError};
+use crate::auth::session::{is_login_page, is_login_url, relogin, session_generation};
+use crate::CONFIG;
 
-const ATTENDANCE_LINK_SELECTOR: &str = r#"a[href^="https://lernplattform.gfn.de/mod/attendance/view.php?id="]"#;
+const ATTENDANCE_LINK_PATH: &str = "/mod/attendance/view.php?id=";
 
 pub async fn create_client() -> Result<(Client, Arc<CookieStoreMutex>), Box<dyn std::error::Error>> {
-    let cookie_store = load_cookie_store();
+    // Unlocking the vault may ask for its passphrase
+    let cookie_store = tokio::task::spawn_blocking(|| load_cookie_store().map_err(|e| e.to_string())).await??;
     let client = Client::builder()
         .cookie_provider(Arc::clone(&cookie_store))
         .gzip(true)
+        .timeout(Duration::from_secs(CONFIG.get_request_timeout_secs()))
         .build()?;
 
     Ok((client, cookie_store))
@@ -25,28 +33,101 @@ lazy_static! {
 }
 
 pub async fn get_body(client: &Client, url: &str) -> Result<String, Box<dyn Error>> {
-    let res = client.get(url).send().await.map_err(|err| {
-        error!("Network Error: {}", err);
-        Box::new(err) as Box<dyn Error>
-    })?;
+    let generation = session_generation().await;
+    let (final_url, body) = get_page(client, url).await?;
 
-    if !res.status().is_success() {
-        error!("Received an error response ({}): {}", res.status(), res.text().await?);
-        return Err("Error response received".into());
-    }
+    // The session expired if we got the login page instead of the requested one
+    let body = if is_login_page(&final_url, &body) && !is_login_url(url) {
+        warn!("Session expired while fetching {}, logging in again", url);
+        relogin(client, generation).await
+            .map_err(|e| RequestError::Auth { reason: format!("Failed to log in again: {}", e) })?;
+
+        let (final_url, body) = get_page(client, url).await?;
+        if is_login_page(&final_url, &body) {
+            return Err(RequestError::Auth { reason: format!("Still redirected to the login page after logging in again: {}", url) }.into());
+        }
 
-    let body = res.text().await.map_err(|err| {
-        error!("Response Error: {}", err);
-        Box::new(err) as Box<dyn Error>
-    })?;
+        body
+    } else {
+        body
+    };
 
     let cleaned_body = RE_H5.replace_all(&body, ""); // H5-Elements are removed from the body
     Ok(cleaned_body.into_owned())
 }
 
+// Fetches the login page without checking the session, the login itself relies on it
+pub async fn get_login_body(client: &Client, url: &str) -> Result<String, Box<dyn Error>> {
+    let (_, body) = get_page(client, url).await?;
+    Ok(body)
+}
+
+// Sends a GET request and returns the final URL (after redirects) and the body, transient errors are retried
+pub async fn get_page(client: &Client, url: &str) -> Result<(Url, String), Box<dyn Error>> {
+    if is_replaying() {
+        let fixture = replay("GET", url)?;
+        let status = StatusCode::from_u16(fixture.status)?;
+
+        if !status.is_success() {
+            return Err(Box::new(RequestError::Status { url: url.to_string(), status }));
+        }
+
+        return Ok((Url::parse(&fixture.final_url)?, fixture.body));
+    }
+
+    let max_attempts = CONFIG.get_max_attempts().max(1);
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let permit = acquire_request_slot().await;
+        let (last_error, delay) = match client.get(url).send().await {
+            Ok(res) if res.status().is_success() => {
+                let final_url = res.url().clone();
+                let body = res.text().await.map_err(|err| {
+                    error!("Response Error: {}", err);
+                    Box::new(err) as Box<dyn Error>
+                })?;
+
+                record("GET", url, None, 200, final_url.as_str(), &body)?;
+                return Ok((final_url, body));
+            }
+            Ok(res) if is_transient_status(res.status()) => (format!("status {}", res.status()), retry_after(&res)),
+            Ok(res) => {
+                let status = res.status();
+                let final_url = res.url().clone();
+                let body = res.text().await?;
+
+                record("GET", url, None, status.as_u16(), final_url.as_str(), &body)?;
+                error!("Received an error response ({}): {}", status, body);
+                return Err(Box::new(RequestError::Status { url: url.to_string(), status }));
+            }
+            Err(err) if is_transient_error(&err) => (err.to_string(), None),
+            Err(err) => {
+                error!("Network Error: {}", err);
+                return Err(Box::new(err));
+            }
+        };
+
+        if attempt >= max_attempts {
+            error!("Request to {} failed after {} attempts: {}", url, attempt, last_error);
+            return Err(Box::new(RequestError::RetriesExhausted { url: url.to_string(), attempts: attempt, last_error }));
+        }
+
+        // Don't hold the request slot while waiting for the retry
+        drop(permit);
+
+        let delay = delay.unwrap_or_else(|| backoff_delay(attempt));
+        warn!("Request to {} failed ({}), retrying in {} ms (attempt {}/{})", url, last_error, delay.as_millis(), attempt, max_attempts);
+        sleep(delay).await;
+    }
+}
+
 pub fn extract_direct_link(body: &String) -> Result<Option<String>, Box<dyn Error>> {
     let document = Html::parse_document(body);
-    let selector = Selector::parse(ATTENDANCE_LINK_SELECTOR).unwrap();
+    let selector = Selector::parse(&format!(r#"a[href^="{}{}"]"#, CONFIG.get_base_url(), ATTENDANCE_LINK_PATH))
+        .map_err(|e| format!("Invalid attendance link selector: {}", e))?;
     let element = document.select(&selector).next();
 
     if let Some(element) = element {
@@ -60,3 +141,68 @@ pub fn extract_direct_link(body: &String) -> Result<Option<String>, Box<dyn Erro
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::{hits, init, mock, MockResponse};
+
+    #[test]
+    fn finds_the_attendance_link_of_the_configured_platform() {
+        let base_url = init();
+        let body = format!(r#"<a href="https://other.example/mod/attendance/view.php?id=1">Other</a><a href="{}/mod/attendance/view.php?id=7">Anwesenheit</a>"#, base_url);
+
+        assert_eq!(extract_direct_link(&body).unwrap(), Some(format!("{}/mod/attendance/view.php?id=7&view=5", base_url)));
+        assert_eq!(extract_direct_link(&"<p>No link</p>".to_string()).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn retries_transient_statuses() {
+        let url = format!("{}/retry/transient", init());
+        mock("/retry/transient", vec![MockResponse::status(503), MockResponse::status(502), MockResponse::json("{}")]);
+
+        let (_, body) = get_page(&Client::new(), &url).await.unwrap();
+
+        assert_eq!(body, "{}");
+        assert_eq!(hits("/retry/transient"), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let url = format!("{}/retry/exhausted", init());
+        mock("/retry/exhausted", vec![MockResponse::status(429)]);
+
+        let error = get_page(&Client::new(), &url).await.err().unwrap();
+
+        assert!(matches!(error.downcast_ref::<RequestError>(), Some(RequestError::RetriesExhausted { attempts: 3, .. })), "{}", error);
+        assert_eq!(hits("/retry/exhausted"), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_other_errors() {
+        let url = format!("{}/retry/not-found", init());
+        mock("/retry/not-found", vec![MockResponse::status(404)]);
+
+        let error = get_page(&Client::new(), &url).await.err().unwrap();
+
+        assert!(matches!(error.downcast_ref::<RequestError>(), Some(RequestError::Status { status: StatusCode::NOT_FOUND, .. })), "{}", error);
+        assert_eq!(hits("/retry/not-found"), 1);
+    }
+
+    #[tokio::test]
+    async fn caps_the_retry_after_delay() {
+        let url = format!("{}/retry/retry-after", init());
+        mock("/retry/retry-after", vec![
+            MockResponse::status(503).header("Retry-After", "3600"),
+            MockResponse::status(429).header("Retry-After", "Fri, 01 Jan 2100 00:00:00 GMT"),
+            MockResponse::json("{}"),
+        ]);
+
+        // The test configuration caps the delays at 50 ms
+        tokio::time::timeout(Duration::from_secs(5), get_page(&Client::new(), &url)).await
+            .expect("the Retry-After delay was not capped")
+            .unwrap();
+
+        assert_eq!(hits("/retry/retry-after"), 3);
+    }
+}
@@ -1,29 +1,60 @@
 use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
 use std::sync::Arc;
-use reqwest_cookie_store::CookieStoreMutex;
+use log::warn;
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use zeroize::{Zeroize, Zeroizing};
+use crate::CONFIG;
+use crate::auth::vault::{with_vault, write_private};
 
-const COOKIE_STORE_PATH: &str = "./cookies.json";
+pub const COOKIE_STORE_PATH: &str = "./cookies.json";
 
-pub fn load_cookie_store() -> Arc<CookieStoreMutex> {
-    let cookie_store = {
-        if let Ok(file) = File::open(COOKIE_STORE_PATH).map(BufReader::new) {
-            reqwest_cookie_store::CookieStore::load_json(file).unwrap()
+// Loads the session cookies from the vault if it is enabled, otherwise from cookies.json
+pub fn load_cookie_store() -> Result<Arc<CookieStoreMutex>, Box<dyn std::error::Error>> {
+    let cookie_store = if CONFIG.is_vault_enabled() {
+        if Path::new(COOKIE_STORE_PATH).exists() {
+            warn!("{} still holds a session in plain text, delete it now that the vault is enabled", COOKIE_STORE_PATH);
+        }
+
+        let cookies = with_vault(|vault| Ok(Zeroizing::new(vault.contents.cookies.clone())))?;
+        if cookies.is_empty() {
+            CookieStore::new(None)
         } else {
-            reqwest_cookie_store::CookieStore::new(None)
+            CookieStore::load_json(cookies.as_bytes()).map_err(|e| format!("Failed to read the cookies of the vault: {}", e))?
         }
+    } else if let Ok(file) = File::open(COOKIE_STORE_PATH).map(BufReader::new) {
+        CookieStore::load_json(file).map_err(|e| format!("Failed to read {}: {}", COOKIE_STORE_PATH, e))?
+    } else {
+        CookieStore::new(None)
     };
 
-    CookieStoreMutex::new(cookie_store).into()
+    Ok(CookieStoreMutex::new(cookie_store).into())
 }
 
+// Saves the session cookies to the vault if it is enabled, otherwise to cookies.json (only readable by the current user)
 pub fn save_cookies(cookie_store: Arc<CookieStoreMutex>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut writer = std::fs::File::create(COOKIE_STORE_PATH)
-        .map(std::io::BufWriter::new)
-        .unwrap();
-
-    let store = cookie_store.lock().unwrap();
-    store.save_json(&mut writer).unwrap();
+    // `save_json` skips session cookies (no expiry), but MoodleSession is one, so every unexpired cookie is written in the same line format
+    let mut json = Zeroizing::new(Vec::new());
+    {
+        let store = cookie_store.lock().map_err(|_| "The cookie store lock is poisoned")?;
+        for cookie in store.iter_unexpired() {
+            let line = Zeroizing::new(serde_json::to_string(cookie)?);
+            json.extend_from_slice(line.as_bytes());
+            json.push(b'\n');
+        }
+    }
 
-    Ok(())
+    if CONFIG.is_vault_enabled() {
+        with_vault(|vault| {
+            let cookies = String::from_utf8(json.to_vec())?;
+            // Assigning would free the previous session without wiping it
+            vault.contents.cookies.zeroize();
+            vault.contents.cookies = cookies;
+            vault.save()
+        })
+    } else {
+        write_private(Path::new(COOKIE_STORE_PATH), &json)?;
+        Ok(())
+    }
 }
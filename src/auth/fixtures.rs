@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use lazy_static::lazy_static;
+use log::{debug, info};
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+const SCRUBBED: &str = "[scrubbed]";
+const SCRUBBED_FORM_FIELDS: [&str; 2] = ["password", "logintoken"];
+
+lazy_static! {
+    // Secrets that end up in URLs and page bodies
+    static ref RE_URL_SECRET: Regex = Regex::new(r#"(?i)\b(wstoken|sesskey|token)=[^&#\s"'<>]+"#).unwrap();
+    static ref RE_BODY_SESSKEY: Regex = Regex::new(r#"(?i)("sesskey"\s*:\s*")[^"]*(")"#).unwrap();
+    static ref RE_SECRET_INPUT: Regex = Regex::new(r#"(?i)<input\b[^>]*\bname\s*=\s*["'](sesskey|logintoken)["'][^>]*>"#).unwrap();
+    static ref RE_INPUT_VALUE: Regex = Regex::new(r#"(?i)\bvalue\s*=\s*("[^"]*"|'[^']*')"#).unwrap();
+}
+
+static FIXTURE_MODE: OnceLock<FixtureMode> = OnceLock::new();
+
+// Mode of the HTTP fixtures, set once at startup
+pub enum FixtureMode {
+    Record(FixtureDir),
+    Replay(FixtureDir),
+}
+
+// A directory of fixtures and how often each one was recorded or replayed through it, a repeated request gets its own file
+pub struct FixtureDir {
+    dir: PathBuf,
+    uses: Mutex<HashMap<PathBuf, u32>>,
+}
+
+// A recorded request/response pair
+#[derive(Serialize, Deserialize)]
+pub struct Fixture {
+    pub method: String,
+    pub url: String,
+    pub form: Option<Value>,
+    pub status: u16,
+    pub final_url: String,
+    pub body: String,
+}
+
+// Sets the fixture mode, has to happen before the first request
+pub fn set_fixture_mode(mode: FixtureMode) -> Result<(), Box<dyn Error>> {
+    match &mode {
+        FixtureMode::Record(fixtures) => {
+            fs::create_dir_all(&fixtures.dir)?;
+            info!("Recording all requests to {}", fixtures.dir.display());
+        }
+        FixtureMode::Replay(fixtures) => {
+            if !fixtures.dir.is_dir() {
+                return Err(format!("Fixture directory {} does not exist", fixtures.dir.display()).into());
+            }
+            info!("Replaying all requests from {}", fixtures.dir.display());
+        }
+    }
+
+    FIXTURE_MODE.set(mode).map_err(|_| "Fixture mode already set".into())
+}
+
+// Check if requests are served from recorded fixtures
+pub fn is_replaying() -> bool {
+    matches!(FIXTURE_MODE.get(), Some(FixtureMode::Replay(_)))
+}
+
+// Stores a request/response pair if recording is enabled, secrets are scrubbed before writing
+pub fn record(method: &str, url: &str, form: Option<Value>, status: u16, final_url: &str, body: &str) -> Result<(), Box<dyn Error>> {
+    match FIXTURE_MODE.get() {
+        Some(FixtureMode::Record(fixtures)) => fixtures.write_fixture(method, url, form, status, final_url, body),
+        _ => Ok(()),
+    }
+}
+
+// Looks up the recorded response for a request
+pub fn replay(method: &str, url: &str) -> Result<Fixture, Box<dyn Error>> {
+    match FIXTURE_MODE.get() {
+        Some(FixtureMode::Replay(fixtures)) => fixtures.read_fixture(method, url),
+        _ => Err("Not in replay mode".into()),
+    }
+}
+
+impl FixtureDir {
+    pub fn new(dir: PathBuf) -> FixtureDir {
+        FixtureDir { dir, uses: Mutex::new(HashMap::new()) }
+    }
+
+    // Writes the next response of a request to the directory, the first one keeps the plain file name
+    fn write_fixture(&self, method: &str, url: &str, form: Option<Value>, status: u16, final_url: &str, body: &str) -> Result<(), Box<dyn Error>> {
+        let fixture = Fixture {
+            method: method.to_string(),
+            url: scrub_url(url),
+            form: form.map(scrub_form),
+            status,
+            final_url: scrub_url(final_url),
+            body: scrub_body(body),
+        };
+
+        let sequence = self.next_sequence(method, url);
+        let path = self.dir.join(fixture_file_name(method, url, sequence));
+        fs::write(&path, serde_json::to_string_pretty(&fixture)?)?;
+        debug!("Recorded {} {} -> {}", method, fixture.url, path.display());
+
+        Ok(())
+    }
+
+    // Reads the responses of a request in the recorded order, the last one is served again once they are used up
+    pub fn read_fixture(&self, method: &str, url: &str) -> Result<Fixture, Box<dyn Error>> {
+        let mut sequence = self.next_sequence(method, url);
+        let mut path = self.dir.join(fixture_file_name(method, url, sequence));
+        while sequence > 1 && !path.exists() {
+            sequence -= 1;
+            path = self.dir.join(fixture_file_name(method, url, sequence));
+        }
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("No recorded response for {} {} ({}): {}", method, scrub_url(url), path.display(), e))?;
+        debug!("Replaying {} {} from {}", method, scrub_url(url), path.display());
+
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    // Counts the uses of a request, starting at 1
+    fn next_sequence(&self, method: &str, url: &str) -> u32 {
+        let mut uses = self.uses.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let sequence = uses.entry(self.dir.join(fixture_file_name(method, url, 1))).or_insert(0);
+        *sequence += 1;
+        *sequence
+    }
+}
+
+// Builds a stable file name from the method and the scrubbed URL, repeated requests get a numbered suffix
+fn fixture_file_name(method: &str, url: &str, sequence: u32) -> String {
+    let hash = fnv1a_hash(&format!("{} {}", method, scrub_url(url)));
+
+    if sequence > 1 {
+        format!("{}-{:016x}-{}.json", method, hash, sequence)
+    } else {
+        format!("{}-{:016x}.json", method, hash)
+    }
+}
+
+// FNV-1a, stable across Rust versions unlike the std hasher
+fn fnv1a_hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn scrub_url(url: &str) -> String {
+    RE_URL_SECRET.replace_all(url, format!("$1={}", SCRUBBED).as_str()).into_owned()
+}
+
+// The sesskey is in links, in the page configuration (M.cfg) and in the hidden inputs of forms, the logintoken in the login form
+fn scrub_body(body: &str) -> String {
+    let body = RE_URL_SECRET.replace_all(body, format!("$1={}", SCRUBBED).as_str());
+    let body = RE_BODY_SESSKEY.replace_all(&body, format!("${{1}}{}${{2}}", SCRUBBED).as_str());
+    RE_SECRET_INPUT.replace_all(&body, |input: &regex::Captures| {
+        RE_INPUT_VALUE.replace_all(&input[0], format!(r#"value="{}""#, SCRUBBED).as_str()).into_owned()
+    }).into_owned()
+}
+
+fn scrub_form(mut form: Value) -> Value {
+    if let Some(fields) = form.as_object_mut() {
+        for field in SCRUBBED_FORM_FIELDS {
+            if let Some(value) = fields.get_mut(field) {
+                *value = Value::String(SCRUBBED.to_string());
+            }
+        }
+    }
+
+    form
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Url;
+    use crate::auth::session::is_login_page;
+    use crate::utils::testing::REGRESSION_FIXTURES;
+
+    #[test]
+    fn scrubs_the_secrets_of_hidden_inputs() {
+        let body = r#"<input type="hidden" name="sesskey" value="abc123"><input value='abc123' type='hidden' name='sesskey'><input type="hidden" name="logintoken" value="abc123"><input type="hidden" name="returnurl" value="/my/">"#;
+
+        let scrubbed = scrub_body(body);
+
+        assert!(!scrubbed.contains("abc123"), "{}", scrubbed);
+        assert_eq!(scrubbed.matches(SCRUBBED).count(), 3);
+        assert!(scrubbed.contains(r#"name="returnurl" value="/my/""#), "{}", scrubbed);
+    }
+
+    #[test]
+    fn scrubs_the_secrets_of_links_and_the_page_configuration() {
+        let body = r#"M.cfg = {"sesskey":"abc123"}; <a href="https://lernplattform.gfn.de/login/logout.php?sesskey=abc123">Logout</a> <img src="/webservice/pluginfile.php?token=def456&file=1">"#;
+
+        let scrubbed = scrub_body(body);
+
+        assert!(!scrubbed.contains("abc123") && !scrubbed.contains("def456"), "{}", scrubbed);
+        assert!(scrubbed.contains(r#"logout.php?sesskey=[scrubbed]">Logout</a>"#), "{}", scrubbed);
+        assert!(scrubbed.contains("token=[scrubbed]&file=1"), "{}", scrubbed);
+        assert_eq!(scrub_url("https://lernplattform.gfn.de/webservice/rest/server.php?wstoken=abc&wsfunction=f"),
+            "https://lernplattform.gfn.de/webservice/rest/server.php?wstoken=[scrubbed]&wsfunction=f");
+    }
+
+    #[test]
+    fn keeps_every_response_of_a_repeated_request() {
+        let dir = std::env::temp_dir().join(format!("moodle-report-portfolio-fixtures-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let url = "https://lernplattform.gfn.de/my/";
+        let recorder = FixtureDir::new(dir.clone());
+        recorder.write_fixture("GET", url, None, 200, "https://lernplattform.gfn.de/login/index.php", "login").unwrap();
+        recorder.write_fixture("GET", url, None, 200, url, "dashboard").unwrap();
+
+        // Only the scrubbed session key differs, so it is the same request sent twice
+        recorder.write_fixture("GET", "https://lernplattform.gfn.de/course/view.php?id=1&sesskey=a", None, 200, "", "first").unwrap();
+        recorder.write_fixture("GET", "https://lernplattform.gfn.de/course/view.php?id=1&sesskey=b", None, 200, "", "second").unwrap();
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+
+        let replayer = FixtureDir::new(dir.clone());
+        let bodies: Vec<String> = (0..3).map(|_| replayer.read_fixture("GET", url).unwrap().body).collect();
+        assert_eq!(bodies, ["login", "dashboard", "dashboard"]);
+
+        let course = "https://lernplattform.gfn.de/course/view.php?id=1&sesskey=c";
+        assert_eq!(replayer.read_fixture("GET", course).unwrap().body, "first");
+        assert_eq!(replayer.read_fixture("GET", course).unwrap().body, "second");
+
+        assert!(replayer.read_fixture("GET", "https://lernplattform.gfn.de/course/view.php?id=2").is_err());
+
+        // Another replay starts again at the first response
+        assert_eq!(FixtureDir::new(dir.clone()).read_fixture("GET", url).unwrap().body, "login");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replays_the_recorded_session_check() {
+        let replayer = FixtureDir::new(PathBuf::from(REGRESSION_FIXTURES));
+        let url = "https://lernplattform.gfn.de/my/";
+
+        // The session check before the login ends on the login page, the one after it on the dashboard
+        let before = replayer.read_fixture("GET", url).unwrap();
+        let after = replayer.read_fixture("GET", url).unwrap();
+
+        assert!(is_login_page(&Url::parse(&before.final_url).unwrap(), &before.body));
+        assert!(!is_login_page(&Url::parse(&after.final_url).unwrap(), &after.body));
+        // The recorded pages contain no secrets
+        assert_eq!(scrub_body(&before.body), before.body);
+        assert_eq!(scrub_body(&after.body), after.body);
+        assert_eq!(after.body.matches(SCRUBBED).count(), 4);
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::{sleep, Duration, Instant};
+use crate::CONFIG;
+
+lazy_static! {
+    // One limiter shared by all requests against the platform
+    static ref LIMITER: RateLimiter = RateLimiter::new(CONFIG.get_requests_per_second(), CONFIG.get_max_in_flight());
+}
+
+// Token bucket for the request rate and a semaphore for the requests in flight
+struct RateLimiter {
+    semaphore: Semaphore,
+    bucket: Mutex<TokenBucket>,
+}
+
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: u32, max_in_flight: usize) -> Self {
+        let rate = requests_per_second as f64;
+
+        Self {
+            semaphore: Semaphore::new(max_in_flight.max(1)),
+            bucket: Mutex::new(TokenBucket { rate, capacity: rate.max(1.0), tokens: rate.max(1.0), last_refill: Instant::now() }),
+        }
+    }
+}
+
+impl TokenBucket {
+    // Takes a token, or returns how long to wait until the next one is available
+    fn try_take(&mut self) -> Option<Duration> {
+        // A rate of 0 disables the rate limit
+        if self.rate <= 0.0 {
+            return None;
+        }
+
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * self.rate).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+// Waits for a free request slot and a token, the slot is released when the permit is dropped
+pub async fn acquire_request_slot() -> SemaphorePermit<'static> {
+    let permit = LIMITER.semaphore.acquire().await.expect("request limiter semaphore closed");
+
+    loop {
+        let wait = LIMITER.bucket.lock().unwrap().try_take();
+        match wait {
+            Some(duration) => sleep(duration).await,
+            None => return permit,
+        }
+    }
+}
@@ -1,13 +1,15 @@
 use lazy_static::lazy_static;
 use log::info;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use scraper::{Html, Selector};
 use crate::{CONFIG};
-use crate::auth::client::get_body;
+use crate::auth::client::get_login_body;
+use crate::auth::fixtures::{is_replaying, record, replay};
+use crate::auth::limiter::acquire_request_slot;
+use crate::auth::session::LOGIN_PATH;
+use crate::auth::secret::account_password;
 use crate::common::tables::LoginForm;
 
-const LOGIN_URL: &str = "https://lernplattform.gfn.de/login/index.php";
-
 lazy_static! {
     static ref LOGINTOKEN_SELECTOR: Selector = Selector::parse(r#"input[name="logintoken"]"#).unwrap();
     static ref ERROR_SELECTOR: Selector = Selector::parse(r#"div.alert.alert-danger"#).unwrap();
@@ -23,27 +25,46 @@ fn extract_logintoken(body: &String) -> Result<String, Box<dyn std::error::Error
     Ok(logintoken)
 }
 
+// The login page of the configured platform
+fn login_url() -> String {
+    format!("{}{}", CONFIG.get_base_url(), LOGIN_PATH)
+}
+
 pub async fn login(client: &Client) -> Result<(), Box<dyn std::error::Error>> {
-    let body = get_body(client, LOGIN_URL).await?;
+    let body = get_login_body(client, &login_url()).await?;
     let logintoken = extract_logintoken(&body)?;
 
+    // The resolved copy is wiped when it goes out of scope, the form wipes its copy when it is dropped
+    let password = account_password().await?;
     let form = LoginForm {
         username: CONFIG.get_user_name().to_owned(),
-        password: CONFIG.get_password().to_owned(),
+        password: password.to_string(),
         logintoken,
     };
+    drop(password);
 
     post_login(client, form).await
 }
 
 async fn post_login(client: &Client, form: LoginForm) -> Result<(), Box<dyn std::error::Error>> {
-    let res = client.post(LOGIN_URL)
-        .form(&form)
-        .send()
-        .await?;
+    let login_url = login_url();
+    let (status, body) = if is_replaying() {
+        let fixture = replay("POST", &login_url)?;
+        (StatusCode::from_u16(fixture.status)?, fixture.body)
+    } else {
+        let _permit = acquire_request_slot().await;
+        let res = client.post(&login_url)
+            .form(&form)
+            .send()
+            .await?;
+
+        let status = res.status();
+        let final_url = res.url().to_string();
+        let body = res.text().await?;
 
-    let status = res.status();
-    let body = res.text().await?;
+        record("POST", &login_url, Some(serde_json::to_value(&form)?), status.as_u16(), &final_url, &body)?;
+        (status, body)
+    };
 
     if status.is_success() {
         let document = Html::parse_document(&body);
@@ -58,3 +79,16 @@ async fn post_login(client: &Client, form: LoginForm) -> Result<(), Box<dyn std:
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::init;
+
+    #[test]
+    fn logs_in_at_the_configured_platform() {
+        let base_url = init();
+
+        assert_eq!(login_url(), format!("{}/login/index.php", base_url));
+    }
+}
@@ -1,3 +1,9 @@
 pub mod client;
 pub mod cookies;
-pub mod login;
\ No newline at end of file
+pub mod fixtures;
+pub mod limiter;
+pub mod login;
+pub mod retry;
+pub mod secret;
+pub mod session;
+pub mod vault;
\ No newline at end of file
@@ -0,0 +1,77 @@
+use std::error::Error;
+use std::fmt;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use tokio::time::Duration;
+use crate::CONFIG;
+
+// Typed errors of a request that could not be completed
+#[derive(Debug)]
+pub enum RequestError {
+    // The server answered with a status that is not worth retrying
+    Status { url: String, status: StatusCode },
+    // All attempts failed with transient errors
+    RetriesExhausted { url: String, attempts: u32, last_error: String },
+    // The platform rejected the login or the web service token, retrying does not help
+    Auth { reason: String },
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Status { url, status } => write!(f, "Error response received ({}) for {}", status, url),
+            RequestError::RetriesExhausted { url, attempts, last_error } => write!(f, "Giving up on {} after {} attempts: {}", url, attempts, last_error),
+            RequestError::Auth { reason } => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for RequestError {}
+
+// Check if an error is a rejected login or token, those fail the whole run instead of a single course
+pub fn is_auth_error(err: &(dyn Error + 'static)) -> bool {
+    matches!(err.downcast_ref::<RequestError>(), Some(RequestError::Auth { .. }))
+}
+
+// Check if a status code is worth retrying
+pub fn is_transient_status(status: StatusCode) -> bool {
+    matches!(status, StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT)
+}
+
+// Check if a network error is worth retrying
+pub fn is_transient_error(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout()
+}
+
+// Reads the Retry-After header (seconds or HTTP date) of a 429 or 503 response, capped like the backoff so the server cannot stall the run
+pub fn retry_after(res: &Response) -> Option<Duration> {
+    if !matches!(res.status(), StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE) {
+        return None;
+    }
+
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let date = DateTime::parse_from_rfc2822(value).ok()?;
+            let seconds = (date.with_timezone(&Utc) - Utc::now()).num_seconds().max(0);
+            Duration::from_secs(seconds as u64)
+        }
+    };
+
+    Some(delay.min(Duration::from_millis(CONFIG.get_retry_max_delay_ms())))
+}
+
+// Exponential backoff with jitter for the given (1-based) attempt
+pub fn backoff_delay(attempt: u32) -> Duration {
+    let base = CONFIG.get_retry_base_delay_ms();
+    let max = CONFIG.get_retry_max_delay_ms();
+
+    let delay = base.saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1))).min(max);
+    let jittered = rand::thread_rng().gen_range(delay / 2..=delay);
+
+    Duration::from_millis(jittered)
+}
@@ -0,0 +1,120 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use log::info;
+use zeroize::{Zeroize, Zeroizing};
+use crate::CONFIG;
+use crate::auth::vault::with_vault;
+
+// The account password for one login, resolved on the blocking pool since the command, the vault and the prompt block
+// It is not kept, the caller drops it once the login form is built; the password of the config file stays in CONFIG and
+// the form body built by reqwest is not zeroized
+pub async fn account_password() -> Result<Zeroizing<String>, Box<dyn Error>> {
+    Ok(tokio::task::spawn_blocking(|| resolve_password().map_err(|e| e.to_string())).await??)
+}
+
+// Resolves the account password from the first configured source, then from the vault, asks for it if there is none
+fn resolve_password() -> Result<Zeroizing<String>, Box<dyn Error>> {
+    if !CONFIG.get_password().is_empty() {
+        return Ok(Zeroizing::new(CONFIG.get_password().to_string()));
+    }
+
+    if !CONFIG.get_password_env().is_empty() {
+        return password_from_env(CONFIG.get_password_env());
+    }
+
+    if !CONFIG.get_password_command().is_empty() {
+        return password_from_command(CONFIG.get_password_command());
+    }
+
+    if !CONFIG.get_password_file().is_empty() {
+        return password_from_file(Path::new(CONFIG.get_password_file()));
+    }
+
+    if CONFIG.is_vault_enabled() {
+        if let Some(password) = with_vault(|vault| Ok(vault.contents.password.clone().map(Zeroizing::new)))? {
+            return Ok(password);
+        }
+    }
+
+    password_from_prompt()
+}
+
+fn password_from_env(name: &str) -> Result<Zeroizing<String>, Box<dyn Error>> {
+    let password = Zeroizing::new(std::env::var(name).map_err(|e| format!("Failed to read the password from ${}: {}", name, e))?);
+
+    if password.is_empty() {
+        return Err(format!("The password variable ${} is empty", name).into());
+    }
+
+    Ok(password)
+}
+
+// Runs the command in the shell and uses the first line of its output, e.g. `pass show lernplattform`
+fn password_from_command(command: &str) -> Result<Zeroizing<String>, Box<dyn Error>> {
+    info!("Reading the password from the password command");
+
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }.map_err(|e| format!("Failed to run the password command: {}", e))?;
+
+    let mut stdout = output.stdout;
+    if !output.status.success() {
+        stdout.zeroize();
+        return Err(format!("The password command failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+
+    first_line(stdout).ok_or_else(|| "The password command printed no password".into())
+}
+
+// Reads the first line of the file, which must not be readable by other users
+fn password_from_file(path: &Path) -> Result<Zeroizing<String>, Box<dyn Error>> {
+    check_permissions(path)?;
+
+    let contents = fs::read(path).map_err(|e| format!("Failed to read the password file {}: {}", path.display(), e))?;
+    first_line(contents).ok_or_else(|| format!("The password file {} is empty", path.display()).into())
+}
+
+#[cfg(unix)]
+pub fn check_permissions(path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path).map_err(|e| format!("Failed to read the password file {}: {}", path.display(), e))?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(format!("The password file {} is accessible by other users (mode {:o}), restrict it with chmod 600", path.display(), mode & 0o777).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn check_permissions(_path: &Path) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+fn password_from_prompt() -> Result<Zeroizing<String>, Box<dyn Error>> {
+    let prompt = format!("Password for {}: ", CONFIG.get_user_name());
+    let password = Zeroizing::new(rpassword::prompt_password(prompt).map_err(|e| format!("Failed to read the password from the terminal: {}", e))?);
+
+    if password.is_empty() {
+        return Err("No password entered".into());
+    }
+
+    Ok(password)
+}
+
+// The first line without the line break, the raw bytes are wiped either way
+fn first_line(bytes: Vec<u8>) -> Option<Zeroizing<String>> {
+    let bytes = Zeroizing::new(bytes);
+    let text = std::str::from_utf8(&bytes).ok()?;
+    let line = text.lines().next().unwrap_or("");
+
+    if line.is_empty() {
+        None
+    } else {
+        Some(Zeroizing::new(line.to_string()))
+    }
+}
@@ -0,0 +1,97 @@
+use std::error::Error;
+use lazy_static::lazy_static;
+use log::{debug, info};
+use reqwest::{Client, Url};
+use tokio::sync::Mutex;
+use crate::auth::client::get_page;
+use crate::auth::login::login;
+use crate::CONFIG;
+
+const DASHBOARD_PATH: &str = "/my/";
+pub const LOGIN_PATH: &str = "/login/index.php";
+const LOGINTOKEN_INPUT: &str = r#"name="logintoken""#;
+
+lazy_static! {
+    static ref SESSION: Mutex<SessionState> = Mutex::new(SessionState { generation: 0, failure: None });
+}
+
+// The generation is incremented on every re-login attempt, so concurrent requests can tell whether someone else already
+// tried again; a failed attempt is kept, so the waiting requests get its error instead of sending the login again
+struct SessionState {
+    generation: u64,
+    failure: Option<String>,
+}
+
+// Checks whether the stored cookies still belong to an authenticated session
+pub async fn probe_session(client: &Client) -> Result<bool, Box<dyn Error>> {
+    let dashboard_url = format!("{}{}", CONFIG.get_base_url(), DASHBOARD_PATH);
+    let (final_url, _) = get_page(client, &dashboard_url).await?;
+
+    // Moodle redirects unauthenticated requests to the login page
+    let authenticated = !final_url.path().starts_with(LOGIN_PATH);
+    debug!("Session probe -> URL: {}, Authenticated: {}", final_url, authenticated);
+
+    Ok(authenticated)
+}
+
+// Returns the current session generation
+pub async fn session_generation() -> u64 {
+    SESSION.lock().await.generation
+}
+
+// Logs in again, unless another request already did so since `seen_generation`
+// A failed re-login is not repeated in this run, wrong credentials would otherwise be sent by every waiting request
+pub async fn relogin(client: &Client, seen_generation: u64) -> Result<(), Box<dyn Error>> {
+    let mut session = SESSION.lock().await;
+
+    if let Some(failure) = &session.failure {
+        return Err(format!("The login already failed in this run: {}", failure).into());
+    }
+    if session.generation != seen_generation {
+        debug!("Session was already renewed by another request");
+        return Ok(());
+    }
+
+    session.generation += 1;
+    if let Err(e) = login(client).await {
+        session.failure = Some(e.to_string());
+        return Err(e);
+    }
+    info!("Logged in again successfully");
+
+    Ok(())
+}
+
+// Check if a response is the login page, either by a redirect or by its login form
+pub fn is_login_page(final_url: &Url, body: &str) -> bool {
+    final_url.path().starts_with(LOGIN_PATH) || body.contains(LOGINTOKEN_INPUT)
+}
+
+// Check if a URL points to the login page itself
+pub fn is_login_url(url: &str) -> bool {
+    Url::parse(url).map_or(false, |url| url.path().starts_with(LOGIN_PATH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::{hits, init, mock, MockResponse};
+
+    #[tokio::test]
+    async fn shares_a_failed_relogin() {
+        init();
+        // The only test that logs in, the login page is broken so the login fails
+        mock(LOGIN_PATH, vec![MockResponse::status(500)]);
+
+        let generation = session_generation().await;
+        let client = Client::new();
+        let results = futures::future::join_all((0..5).map(|_| relogin(&client, generation))).await;
+
+        assert!(results.iter().all(Result::is_err));
+        assert_eq!(hits(LOGIN_PATH), 1);
+
+        // A later request does not try again either
+        assert!(relogin(&client, session_generation().await).await.is_err());
+        assert_eq!(hits(LOGIN_PATH), 1);
+    }
+}
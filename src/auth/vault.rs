@@ -0,0 +1,327 @@
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use lazy_static::lazy_static;
+use log::info;
+use rand::RngCore;
+use serde_derive::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+use crate::CONFIG;
+
+// Increased on every incompatible change of the file format
+const VAULT_VERSION: u32 = 1;
+// Binds the ciphertext to this file format, a vault of another program does not decrypt
+const ASSOCIATED_DATA: &[u8] = b"moodle-report-portfolio vault";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+
+// Upper bounds of the key derivation parameters read from the file, a manipulated file could otherwise make the
+// unlock allocate gigabytes or run for hours (256 MiB, 16 passes, 8 lanes, the defaults are far below)
+const MAX_M_COST: u32 = 256 * 1024;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 8;
+
+lazy_static! {
+    // The vault unlocked by this run, the passphrase is asked for only once
+    static ref UNLOCKED_VAULT: Mutex<Option<Vault>> = Mutex::new(None);
+}
+
+// The encrypted file, the key is derived from the passphrase with Argon2id
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+// The decrypted contents, wiped when they are dropped
+#[derive(Default, Serialize, Deserialize)]
+pub struct VaultContents {
+    #[serde(default)]
+    pub cookies: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+impl Drop for VaultContents {
+    fn drop(&mut self) {
+        self.cookies.zeroize();
+        self.password.zeroize();
+    }
+}
+
+pub struct Vault {
+    path: PathBuf,
+    params: Params,
+    salt: [u8; SALT_LENGTH],
+    key: Zeroizing<[u8; 32]>,
+    pub contents: VaultContents,
+}
+
+impl Vault {
+    // An empty vault, it is written on the first save
+    pub fn create(path: &Path, passphrase: &str) -> Result<Vault, Box<dyn Error>> {
+        let params = Params::default();
+        let salt = random_bytes::<SALT_LENGTH>();
+        let key = derive_key(passphrase, &salt, &params)?;
+
+        Ok(Vault { path: path.to_path_buf(), params, salt, key, contents: VaultContents::default() })
+    }
+
+    pub fn open(path: &Path, passphrase: &str) -> Result<Vault, Box<dyn Error>> {
+        let file: VaultFile = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| format!("The vault {} is damaged: {}", path.display(), e))?;
+
+        if file.version != VAULT_VERSION {
+            return Err(format!("The vault {} has the unsupported version {}", path.display(), file.version).into());
+        }
+
+        if file.m_cost > MAX_M_COST || file.t_cost > MAX_T_COST || file.p_cost > MAX_P_COST {
+            return Err(format!("The vault {} asks for key derivation parameters above the limits (m_cost {}, t_cost {}, p_cost {})",
+                path.display(), MAX_M_COST, MAX_T_COST, MAX_P_COST).into());
+        }
+
+        let params = Params::new(file.m_cost, file.t_cost, file.p_cost, Some(32)).map_err(|e| format!("Invalid key derivation parameters: {}", e))?;
+        let salt: [u8; SALT_LENGTH] = decode(&file.salt)?;
+        let nonce: [u8; NONCE_LENGTH] = decode(&file.nonce)?;
+        let ciphertext = BASE64.decode(&file.ciphertext)?;
+        let key = derive_key(passphrase, &salt, &params)?;
+        let aad = associated_data(&params, &salt);
+
+        let plaintext = Zeroizing::new(XChaCha20Poly1305::new(key.as_ref().into())
+            .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+            .map_err(|_| format!("Failed to decrypt the vault {}: wrong passphrase or damaged file", path.display()))?);
+        let contents = serde_json::from_slice(&plaintext)?;
+
+        Ok(Vault { path: path.to_path_buf(), params, salt, key, contents })
+    }
+
+    // Encrypts the contents with a fresh nonce and replaces the file atomically
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let nonce = random_bytes::<NONCE_LENGTH>();
+        let plaintext = Zeroizing::new(serde_json::to_vec(&self.contents)?);
+        let aad = associated_data(&self.params, &self.salt);
+
+        let ciphertext = XChaCha20Poly1305::new(self.key.as_ref().into())
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &aad })
+            .map_err(|_| "Failed to encrypt the vault")?;
+
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            m_cost: self.params.m_cost(),
+            t_cost: self.params.t_cost(),
+            p_cost: self.params.p_cost(),
+            salt: BASE64.encode(self.salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+
+        write_private(&self.path, &serde_json::to_vec_pretty(&file)?)?;
+        Ok(())
+    }
+
+    // Derives a new key from the passphrase with a new salt, the file is rewritten by the next save
+    pub fn rekey(&mut self, passphrase: &str) -> Result<(), Box<dyn Error>> {
+        self.params = Params::default();
+        self.salt = random_bytes::<SALT_LENGTH>();
+        self.key = derive_key(passphrase, &self.salt, &self.params)?;
+
+        Ok(())
+    }
+}
+
+// Runs `f` with the vault of the configuration, unlocking (or creating) it on the first use
+// The lock is held while the passphrase is read, so concurrent callers wait for the first unlock instead of asking again;
+// async callers run this on the blocking pool since the prompt blocks
+pub fn with_vault<T>(f: impl FnOnce(&mut Vault) -> Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+    let mut unlocked = UNLOCKED_VAULT.lock().map_err(|_| "The vault lock is poisoned")?;
+    if unlocked.is_none() {
+        *unlocked = Some(unlock_vault(Path::new(CONFIG.get_vault_path()))?);
+    }
+
+    f(unlocked.as_mut().unwrap())
+}
+
+fn unlock_vault(path: &Path) -> Result<Vault, Box<dyn Error>> {
+    if path.exists() {
+        let passphrase = read_passphrase("Vault passphrase: ", false)?;
+        let vault = Vault::open(path, &passphrase)?;
+        info!("Unlocked the vault {}", path.display());
+        Ok(vault)
+    } else {
+        info!("Creating the vault {}", path.display());
+        let passphrase = read_passphrase("New vault passphrase: ", true)?;
+        Vault::create(path, &passphrase)
+    }
+}
+
+// Reads the passphrase from the configured environment variable, otherwise asks for it (twice for a new one)
+pub fn read_passphrase(prompt: &str, confirm: bool) -> Result<Zeroizing<String>, Box<dyn Error>> {
+    let name = CONFIG.get_vault_passphrase_env();
+    if !name.is_empty() {
+        let passphrase = Zeroizing::new(std::env::var(name).map_err(|e| format!("Failed to read the vault passphrase from ${}: {}", name, e))?);
+        if passphrase.is_empty() {
+            return Err(format!("The vault passphrase variable ${} is empty", name).into());
+        }
+        return Ok(passphrase);
+    }
+
+    prompt_passphrase(prompt, confirm)
+}
+
+// Asks for a passphrase on the terminal, a new one has to be repeated
+pub fn prompt_passphrase(prompt: &str, confirm: bool) -> Result<Zeroizing<String>, Box<dyn Error>> {
+    let passphrase = Zeroizing::new(rpassword::prompt_password(prompt)?);
+    if passphrase.is_empty() {
+        return Err("The vault passphrase must not be empty".into());
+    }
+
+    if confirm {
+        let repeated = Zeroizing::new(rpassword::prompt_password("Repeat the passphrase: ")?);
+        if *repeated != *passphrase {
+            return Err("The passphrases do not match".into());
+        }
+    }
+
+    Ok(passphrase)
+}
+
+// Overwrites the vault with zeros before deleting it, copy-on-write file systems and SSDs may still keep old blocks
+pub fn wipe_vault(path: &Path) -> Result<bool, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    let length = fs::metadata(path)?.len() as usize;
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0; length])?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path)?;
+    if let Ok(mut unlocked) = UNLOCKED_VAULT.lock() {
+        *unlocked = None;
+    }
+
+    Ok(true)
+}
+
+// Writes the file only readable by the current user, through a temporary file that replaces it atomically
+pub fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&temp_path)?;
+    // A leftover temporary file keeps its old mode, so the permissions are set again
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: &Params) -> Result<Zeroizing<[u8; 32]>, Box<dyn Error>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| format!("Failed to derive the vault key: {}", e))?;
+
+    Ok(key)
+}
+
+// The header of the file as associated data, changing the parameters or the salt makes the decryption fail
+fn associated_data(params: &Params, salt: &[u8]) -> Vec<u8> {
+    let mut data = ASSOCIATED_DATA.to_vec();
+    for value in [VAULT_VERSION, params.m_cost(), params.t_cost(), params.p_cost()] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(salt);
+
+    data
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn decode<const N: usize>(value: &str) -> Result<[u8; N], Box<dyn Error>> {
+    BASE64.decode(value)?.try_into().map_err(|_| "The vault has a malformed salt or nonce".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn saved_vault(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("moodle-report-portfolio-vault-{}-{}.json", name, std::process::id()));
+        let mut vault = Vault::create(&path, "passphrase").unwrap();
+        vault.contents.cookies = "MoodleSession".to_string();
+        vault.save().unwrap();
+        path
+    }
+
+    fn edit_header(path: &Path, key: &str, value: Value) {
+        let mut file: Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        file[key] = value;
+        fs::write(path, serde_json::to_vec(&file).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn opens_a_saved_vault() {
+        let path = saved_vault("open");
+
+        assert_eq!(Vault::open(&path, "passphrase").unwrap().contents.cookies, "MoodleSession");
+        assert!(Vault::open(&path, "wrong").is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_changed_header() {
+        let path = saved_vault("header");
+
+        edit_header(&path, "version", Value::from(VAULT_VERSION + 1));
+        let error = Vault::open(&path, "passphrase").err().unwrap().to_string();
+        assert!(error.contains("unsupported version"), "{}", error);
+
+        edit_header(&path, "version", Value::from(VAULT_VERSION));
+        edit_header(&path, "salt", Value::from(BASE64.encode(random_bytes::<SALT_LENGTH>())));
+        let error = Vault::open(&path, "passphrase").err().unwrap().to_string();
+        assert!(error.contains("Failed to decrypt"), "{}", error);
+
+        edit_header(&path, "m_cost", Value::from(MAX_M_COST + 1));
+        let error = Vault::open(&path, "passphrase").err().unwrap().to_string();
+        assert!(error.contains("above the limits"), "{}", error);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
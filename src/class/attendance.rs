@@ -1,29 +1,148 @@
+use std::collections::HashSet;
 use std::error::Error;
 use std::sync::Arc;
-use log::debug;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
 use scraper::{Html, Selector};
-use reqwest::Client;
+use reqwest::{Client, Url};
 use crate::auth::client::get_body;
-use crate::common::tables::Attendance;
+use crate::common::parse::{parse_date, parse_duration, ParseError, ATTENDANCE_DATE_FORMAT};
+use crate::common::tables::{Attendance, AttendanceStatus, TimeRange};
+use crate::utils::config::CONFIG_DATE_FORMAT;
 use crate::CONFIG;
 
 const ATTENDANCE_PATH: &str = "/local/anmeldung/anwesenheit.php?page=1";
 
-// This function scrapes the attendance from the course attendance page
+lazy_static! {
+    static ref NEXT_LINK_SELECTOR: Selector = Selector::parse(r#"li.page-item a[rel="next"]"#).unwrap();
+    static ref PAGE_ITEM_SELECTOR: Selector = Selector::parse("li.page-item").unwrap();
+    static ref PAGE_LINK_SELECTOR: Selector = Selector::parse("a[href]").unwrap();
+}
+
+// This function scrapes the attendance from the course attendance page, following the pagination until the last page
+// or until a page reaches the configured cutoff date (the newest records come first)
 pub async fn scrape_attendance(client: Arc<Client>) -> Result<Vec<Attendance>, Box<dyn Error>> {
-    let attendance_url = format!("{}{}", CONFIG.get_base_url(), ATTENDANCE_PATH);
-    let body = get_body(&*client, &attendance_url).await?;
-    extract_attendance(&body)
+    let cutoff = CONFIG.get_attendance_cutoff_date();
+
+    let mut attendance_url = Some(format!("{}{}", CONFIG.get_base_url(), ATTENDANCE_PATH));
+    let mut visited = HashSet::new();
+    let mut attendance_records = Vec::new();
+
+    while let Some(url) = attendance_url.take() {
+        if !visited.insert(url.clone()) {
+            warn!("The attendance pagination links back to {}, stopping", url);
+            break;
+        }
+
+        let body = get_body(&*client, &url).await?;
+        let mut page_records = extract_attendance(&body, &url)?;
+        debug!("Attendance page {} -> {} records", url, page_records.len());
+
+        if let Some(cutoff) = cutoff {
+            let page_len = page_records.len();
+            page_records.retain(|attendance| attendance.date >= cutoff);
+
+            if page_records.len() < page_len {
+                attendance_records.extend(page_records);
+                info!("Reached the attendance cutoff date {}", cutoff.format(CONFIG_DATE_FORMAT));
+                break;
+            }
+        }
+
+        attendance_records.extend(page_records);
+        attendance_url = next_page_url(&body, &url)?;
+    }
+
+    info!("Scraped {} attendance pages", visited.len());
+    Ok(merge_attendance(attendance_records))
+}
+
+// The link of the next page in the pagination controls, either marked with rel="next" or the page after the active one
+fn next_page_url(body: &str, current_url: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let fragment = Html::parse_document(body);
+
+    let href = match fragment.select(&NEXT_LINK_SELECTOR).next() {
+        Some(link) => link.value().attr("href"),
+        None => fragment.select(&PAGE_ITEM_SELECTOR)
+            .skip_while(|item| !item.value().classes().any(|class| class == "active"))
+            .nth(1)
+            .and_then(|item| item.select(&PAGE_LINK_SELECTOR).next())
+            .and_then(|link| link.value().attr("href")),
+    };
+
+    match href {
+        Some(href) if !href.is_empty() && href != "#" => Ok(Some(Url::parse(current_url)?.join(href)?.to_string())),
+        _ => Ok(None),
+    }
+}
+
+// Rows of the same day can be repeated across a page boundary, they are merged into one record
+// A day with times wins over an absence, differing times are combined into the span of both
+fn merge_attendance(attendances: Vec<Attendance>) -> Vec<Attendance> {
+    let mut merged: Vec<Attendance> = Vec::new();
+
+    for attendance in attendances {
+        match merged.iter_mut().find(|existing| existing.date == attendance.date) {
+            Some(existing) => {
+                existing.time = match (existing.time, attendance.time) {
+                    (Some(a), Some(b)) => Some(TimeRange { from: a.from.min(b.from), to: a.to.max(b.to) }),
+                    (a, b) => a.or(b),
+                };
+                if existing.status.is_absence() && !attendance.status.is_absence() {
+                    existing.status = attendance.status;
+                }
+                if existing.remarks.is_empty() {
+                    existing.remarks = attendance.remarks;
+                }
+                existing.duration = existing.duration.max(attendance.duration);
+                debug!("Merged duplicate attendance row of {}", attendance.date.format(ATTENDANCE_DATE_FORMAT));
+            }
+            None => merged.push(attendance),
+        }
+    }
+
+    merged
+}
+
+// Columns of the attendance table, found by their header so added or reordered columns don't break the extraction
+struct ColumnMap {
+    date: usize,
+    from_time: usize,
+    to_time: usize,
+    status: Option<usize>,
+    remarks: Option<usize>,
+    duration: Option<usize>,
+}
+
+impl ColumnMap {
+    // The layout of the attendance page without a recognizable header
+    fn fallback() -> ColumnMap {
+        ColumnMap { date: 1, from_time: 2, to_time: 3, status: None, remarks: None, duration: None }
+    }
+
+    fn from_headers(headers: &[String]) -> ColumnMap {
+        let find = |keywords: &[&str]| headers.iter().position(|header| keywords.iter().any(|keyword| header.contains(keyword)));
+        let fallback = ColumnMap::fallback();
+
+        ColumnMap {
+            date: find(&["datum", "date"]).unwrap_or(fallback.date),
+            from_time: find(&["von", "beginn", "kommen", "from"]).unwrap_or(fallback.from_time),
+            to_time: find(&["bis", "ende", "gehen", "to"]).unwrap_or(fallback.to_time),
+            status: find(&["status", "anwesenheit"]),
+            remarks: find(&["bemerkung", "notiz", "grund", "remark"]),
+            duration: find(&["dauer", "stunden", "duration"]),
+        }
+    }
 }
 
-// This function extracts the attendance information from the HTML content of the page
-pub fn extract_attendance(body: &str) -> Result<Vec<Attendance>, Box<dyn Error>> {
+// This function extracts the attendance information from the HTML content of the page, rows with an invalid date are skipped
+pub fn extract_attendance(body: &str, source_url: &str) -> Result<Vec<Attendance>, Box<dyn Error>> {
     let fragment = Html::parse_document(body);
 
     // Define selectors
     let table_selector = Selector::parse(".table")?;
     let row_selector = Selector::parse("tr")?;
-    let cell_selector = Selector::parse("td")?;
+    let cell_selector = Selector::parse("th, td")?;
 
     // Find the table
     let table = fragment.select(&table_selector).next().ok_or("Attendance table not found")?;
@@ -32,29 +151,124 @@ pub fn extract_attendance(body: &str) -> Result<Vec<Attendance>, Box<dyn Error>>
     let mut attendance_records = Vec::new();
     let mut rows = table.select(&row_selector);
 
-    // Skip the first row (header)
-    if let Some(_) = rows.next() {
-        for row_element in rows {
-            let cells: Vec<_> = row_element.select(&cell_selector).collect();
+    let cell_texts = |row: scraper::ElementRef| -> Vec<String> {
+        row.select(&cell_selector).map(|cell| cell.text().collect::<String>().trim().to_string()).collect()
+    };
 
-            // Collect the cells into a vector
-            let mut cell_contents: Vec<String> = Vec::new();
-            for cell in &cells {
-                cell_contents.push(cell.inner_html().trim().to_string());
-            }
+    // The first row is the header
+    let columns = match rows.next() {
+        Some(header) => ColumnMap::from_headers(&cell_texts(header).iter().map(|header| header.to_lowercase()).collect::<Vec<_>>()),
+        None => return Ok(attendance_records),
+    };
+
+    for row_element in rows {
+        let cells = cell_texts(row_element);
+        let cell = |index: Option<usize>| index.and_then(|index| cells.get(index)).map_or("", |text| text.as_str());
 
-            // Create an attendance record
-            let attendance = Attendance {
-                date: cells[1].text().collect::<String>().trim().to_string(),
-                from_time: cells[2].text().collect::<String>().trim().to_string(),
-                to_time: cells[3].text().collect::<String>().trim().to_string(),
-            };
-            attendance_records.push(attendance);
+        let row = AttendanceRow {
+            date: cell(Some(columns.date)),
+            from_time: cell(Some(columns.from_time)),
+            to_time: cell(Some(columns.to_time)),
+            status: cell(columns.status),
+            remarks: cell(columns.remarks),
+            duration: cell(columns.duration),
+        };
 
-            debug!("Attendance -> Date: {}, From: {}, To: {}",
-                  cell_contents[1], cell_contents[2], cell_contents[3]);
+        match parse_attendance(&row, source_url) {
+            Ok(attendance) => {
+                debug!("Attendance -> Date: {}, From: {}, To: {}, Status: {}, Remarks: {}",
+                      row.date, row.from_time, row.to_time, attendance.status, attendance.remarks);
+                attendance_records.push(attendance);
+            }
+            Err(e) => warn!("Skipping attendance row: {}", e),
         }
     }
 
     Ok(attendance_records)
 }
+
+// The raw cells of an attendance row, missing columns are empty
+struct AttendanceRow<'a> {
+    date: &'a str,
+    from_time: &'a str,
+    to_time: &'a str,
+    status: &'a str,
+    remarks: &'a str,
+    duration: &'a str,
+}
+
+// Absences are listed without times (empty or a placeholder like "-"), times that cannot be read count as missing,
+// without a status column (or with an unknown status) the status is inferred from the times
+fn parse_attendance(row: &AttendanceRow, source_url: &str) -> Result<Attendance, ParseError> {
+    let date = parse_date(row.date, ATTENDANCE_DATE_FORMAT, source_url)?;
+
+    let has_time = |time: &str| time.chars().any(|c| c.is_ascii_digit());
+    let time = if has_time(row.from_time) || has_time(row.to_time) {
+        let raw_time = format!("{} - {}", row.from_time, row.to_time);
+        match TimeRange::parse(&raw_time) {
+            Ok(time) => Some(time),
+            Err(e) => {
+                warn!("Ignoring the attendance times: {}", ParseError::new(e, &raw_time, source_url));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let status = AttendanceStatus::parse(row.status).unwrap_or_else(|| {
+        let inferred = if time.is_some() { AttendanceStatus::Present } else { AttendanceStatus::Absent };
+        if !row.status.is_empty() {
+            warn!("Inferring {} from the times: {}", inferred, ParseError::new("unknown attendance status", row.status, source_url));
+        }
+        inferred
+    });
+
+    let duration = parse_duration(row.duration)
+        .or_else(|| time.map(|time| (time.to - time.from).num_minutes() as u32));
+
+    Ok(Attendance { date, time, status, remarks: row.remarks.to_string(), duration })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use chrono::NaiveDate;
+    use crate::auth::fixtures::FixtureDir;
+    use crate::utils::testing::REGRESSION_FIXTURES;
+
+    const FIRST_PAGE: &str = "https://lernplattform.gfn.de/local/anmeldung/anwesenheit.php?page=1";
+    const SECOND_PAGE: &str = "https://lernplattform.gfn.de/local/anmeldung/anwesenheit.php?page=2";
+
+    fn date(raw: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(raw, ATTENDANCE_DATE_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn parses_the_recorded_attendance_pages() {
+        let replayer = FixtureDir::new(PathBuf::from(REGRESSION_FIXTURES));
+        let first = replayer.read_fixture("GET", FIRST_PAGE).unwrap();
+        let records = extract_attendance(&first.body, FIRST_PAGE).unwrap();
+
+        let summary: Vec<(NaiveDate, AttendanceStatus, Option<String>, &str, Option<u32>)> = records.iter()
+            .map(|record| (record.date, record.status, record.time.map(|time| time.to_string()), record.remarks.as_str(), record.duration))
+            .collect();
+        assert_eq!(summary, vec![
+            (date("11.09.2023"), AttendanceStatus::Present, Some("08:31 - 16:02".to_string()), "", Some(451)),
+            (date("08.09.2023"), AttendanceStatus::Excused, None, "Arzttermin", None),
+            // Times that cannot be read count as missing, without a status the day is an absence
+            (date("07.09.2023"), AttendanceStatus::Absent, None, "Zeiterfassung defekt", None),
+            (date("06.09.2023"), AttendanceStatus::Late, Some("09:15 - 16:00".to_string()), "Bahn", Some(405)),
+        ]);
+        assert_eq!(next_page_url(&first.body, FIRST_PAGE).unwrap().as_deref(), Some(SECOND_PAGE));
+
+        let second = replayer.read_fixture("GET", SECOND_PAGE).unwrap();
+        let records = extract_attendance(&second.body, SECOND_PAGE).unwrap();
+
+        // An unknown status is inferred from the times
+        let statuses: Vec<(NaiveDate, AttendanceStatus)> = records.iter().map(|record| (record.date, record.status)).collect();
+        assert_eq!(statuses, vec![(date("05.09.2023"), AttendanceStatus::Present), (date("04.09.2023"), AttendanceStatus::Absent)]);
+        assert_eq!(next_page_url(&second.body, SECOND_PAGE).unwrap(), None);
+    }
+}
@@ -3,13 +3,16 @@ use reqwest::Client;
 use std::result::Result;
 use scraper::{ElementRef, Html, Selector};
 use crate::auth::client::{extract_direct_link, get_body};
+use crate::auth::retry::is_auth_error;
 use futures::stream::FuturesUnordered;
 use futures::stream::StreamExt;
 use lazy_static::lazy_static;
 use html_escape::decode_html_entities;
 use linked_hash_set::LinkedHashSet;
-use log::debug;
-use crate::common::tables::{Classbook, ClassbookEntry};
+use chrono::{NaiveDate, NaiveTime, Weekday};
+use log::{debug, error, warn};
+use crate::common::parse::{parse_date, parse_weekday, ParseError, ENTRY_DATE_FORMAT};
+use crate::common::tables::{Classbook, ClassbookEntry, TimeRange};
 use crate::utils::replacement::apply_replacements;
 
 lazy_static! {
@@ -26,10 +29,12 @@ lazy_static! {
     static ref LI_SELECTOR: Selector = Selector::parse("li").unwrap();
     static ref H2_IN_TD_SELECTOR: Selector = Selector::parse("td > h2").unwrap();
     static ref TD_UL_LI_SPAN_SELECTOR: Selector = Selector::parse("td ul li span").unwrap();
+    static ref TR_SELECTOR: Selector = Selector::parse("tr").unwrap();
 }
 
-// Extracts the classbook entries from the given HTML body
-pub async fn extract_classbook(client: &Client, body: &String) -> Result<Classbook, Box<dyn Error>> {
+// Extracts all classbooks linked on the course page, in page order, together with the IDs of the failed ones
+// A failing classbook is reported and skipped, the course only fails if none of its classbooks could be extracted
+pub async fn extract_classbooks(client: &Client, body: &String) -> Result<(Vec<Classbook>, Vec<String>), Box<dyn Error>> {
     let document = Html::parse_document(body);
 
     let elements: Vec<_> = document.select(&LIST_GROUP_ITEM_SELECTOR)
@@ -44,7 +49,7 @@ pub async fn extract_classbook(client: &Client, body: &String) -> Result<Classbo
     }
 
     let mut tasks = FuturesUnordered::new();
-    for element in elements {
+    for (position, element) in elements.into_iter().enumerate() {
         let client = client.clone();
 
         let a_element = element.select(&A_SELECTOR).next()
@@ -57,29 +62,51 @@ pub async fn extract_classbook(client: &Client, body: &String) -> Result<Classbo
             .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "classbook data-key not found"))?.to_string();
 
         tasks.push(async move {
-            let classbook_body = get_body(&client, &link).await?;
-            let direct_link = extract_direct_link(&classbook_body)?;
+            let result = async {
+                let classbook_body = get_body(&client, &link).await?;
+                let direct_link = extract_direct_link(&classbook_body)?;
 
-            let direct_link = direct_link.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "direct link not found"))?;
+                let direct_link = direct_link.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "direct link not found"))?;
 
-            let entries = extract_classbook_entries(&client, &[direct_link.clone()]).await?;
+                let entries = extract_classbook_entries(&client, &[direct_link.clone()]).await?;
 
-            debug!("Classbook -> ID {}, Link: {}, Direct Link: {}", id, link, &direct_link);
+                debug!("Classbook -> ID {}, Link: {}, Direct Link: {}", id, link, &direct_link);
 
-            Ok::<_, Box<dyn Error>>(Classbook { link, id, direct_link: Some(direct_link), entries })
+                Ok::<_, Box<dyn Error>>(Classbook { link: link.clone(), id: id.clone(), direct_link: Some(direct_link), entries })
+            }.await;
+
+            (position, id, result)
         });
     }
 
     let mut results = Vec::new();
     while let Some(result) = tasks.next().await {
+        results.push(result);
+    }
+
+    // The tasks finish in any order, the page order keeps the result deterministic
+    results.sort_by_key(|(position, _, _)| *position);
+
+    let mut classbooks = Vec::new();
+    let mut failed = Vec::new();
+    let mut failures = Vec::new();
+    for (_, id, result) in results {
         match result {
-            Ok(classbook) => results.push(classbook),
-            Err(e) => eprintln!("Failed to extract classbook: {:?}", e),
+            Ok(classbook) => classbooks.push(classbook),
+            Err(e) if is_auth_error(&*e) => return Err(e),
+            Err(e) => {
+                error!("Failed to extract classbook {}: {}", id, e);
+                failures.push(format!("classbook {}: {}", id, e));
+                failed.push(id);
+            }
         }
     }
 
-    // Return the first classbook
-    results.into_iter().next().ok_or_else(|| Box::new(std::io::Error::new(std::io::ErrorKind::Other, "no classbook processed")) as Box<dyn Error>)
+    if classbooks.is_empty() {
+        return Err(format!("no classbook processed ({})", failures.join(", ")).into());
+    }
+
+    Ok((classbooks, failed))
 }
 
 pub async fn extract_classbook_entries(client: &Client, direct_links: &[String]) -> Result<Vec<ClassbookEntry>, Box<dyn Error>> {
@@ -92,7 +119,7 @@ pub async fn extract_classbook_entries(client: &Client, direct_links: &[String])
         tasks.push(async move {
             let body = get_body(&client, &link).await?;
             let document = Html::parse_document(&body);
-            extract_classbook_entry(document).await
+            extract_classbook_entry(document, &link).await
         });
     }
 
@@ -101,8 +128,10 @@ pub async fn extract_classbook_entries(client: &Client, direct_links: &[String])
         results.push(result);
     }
 
+    // A failing page fails the classbook, so it is reported instead of silently missing entries
     let entries = results.into_iter()
-        .filter_map(|result| result.ok())
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
         .flatten()
         .collect::<Vec<ClassbookEntry>>();
 
@@ -164,70 +193,85 @@ async fn process_element(element: ElementRef<'_>, selectors: &[&Selector]) -> Li
     return activities;
 }
 
-// Function to parse the date and time from the HTML document
-async fn extract_classbook_entry(document: Html) -> Result<Vec<ClassbookEntry>, Box<dyn Error>> {
+// Function to parse the date and time from the HTML document, rows with an invalid date or time are skipped
+async fn extract_classbook_entry(document: Html, source_url: &str) -> Result<Vec<ClassbookEntry>, Box<dyn Error>> {
     let mut entries = Vec::new();
 
     for element in document.select(&TABLE_SELECTOR) {
         let date_and_time_str = select_element_and_extract_text(&element, &DATE_AND_TIME_SELECTOR)?;
         let description = select_element_and_extract_text(&element, &DESCRIPTION_SELECTOR)?;
 
-        let (weekday, date, mut time) = parse_date_and_time(&date_and_time_str);
+        let (weekday, date, time) = match parse_date_and_time(&date_and_time_str, source_url) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("Skipping classbook row: {}", e);
+                continue;
+            }
+        };
 
         // Extract the activities from the description by selecting the <p>, <li>, and <td> elements
         let mut activities = LinkedHashSet::new();
         activities.extend(process_element(element, &[&P_SELECTOR, &LI_SELECTOR, &TD_SELECTOR, &P_SPAN_SELECTOR]).await);
 
         // Check if the time range is valid
-        time = limit_time_range(&time);
+        let time = limit_time_range(time);
 
         // Print out all activities to be able to see if the splitting worked correctly
         for activity in &activities {
-            debug!("Activities -> Weekday: {}, Date: {}, Time: {}, Activity: {}", weekday, date, time, activity);
+            debug!("Activities -> Weekday: {}, Date: {}, Time: {}, Activity: {}", weekday, date.format(ENTRY_DATE_FORMAT), time, activity);
         }
 
-        entries.push(ClassbookEntry { weekday, date, time, description, activities });
+        entries.push(ClassbookEntry { weekday, date, time: Some(time), description, activities, hours: None, learning_field: None });
     }
 
     Ok(entries)
 }
 
-// Function to limit the time range to "08:00 - 16:30"
-fn limit_time_range(time: &str) -> String {
-    // Check if time is empty and set it to "08:00 - 16:30" if true
-    if time.is_empty() {
-        return "08:00 - 16:30".to_string();
-    }
+// Function to extract the description and the activities from a raw session description (e.g. from the web services)
+pub async fn extract_description_activities(description_html: &str) -> Result<(String, LinkedHashSet<String>), Box<dyn Error>> {
+    // Wrap the description in a table row, so it is processed exactly like a scraped classbook row
+    let document = Html::parse_document(&format!(
+        r#"<table><tbody><tr><td class="desccol cell c1">{}</td></tr></tbody></table>"#,
+        description_html
+    ));
 
-    // Split the time into start and end time
-    let split_time: Vec<&str> = time.split(" - ").collect();
-    if split_time.len() == 2 {
-        let start_time = split_time[0].trim();
-        let end_time = split_time[1].trim();
+    let element = document.select(&TR_SELECTOR).next().ok_or("no description row found")?;
+    let description = select_element_and_extract_text(&element, &DESCRIPTION_SELECTOR)?;
+    let activities = process_element(element, &[&P_SELECTOR, &LI_SELECTOR, &TD_SELECTOR, &P_SPAN_SELECTOR]).await;
 
-        // Check if end time is greater than "16:30"
-        if end_time > "16:30" {
-            // Set end time to "16:30"
-            return format!("{} - 16:30", start_time);
-        }
-    }
+    Ok((description, activities))
+}
 
-    return time.to_string();
+// Function to limit the time range to "08:00 - 16:30"
+pub fn limit_time_range(time: Option<TimeRange>) -> TimeRange {
+    let latest_end = NaiveTime::from_hms_opt(16, 30, 0).unwrap();
+
+    match time {
+        // Set the time to "08:00 - 16:30" if it is missing
+        None => TimeRange { from: NaiveTime::from_hms_opt(8, 0, 0).unwrap(), to: latest_end },
+        // Set the end time to "16:30" if it is later
+        Some(time) if time.to > latest_end => TimeRange { from: time.from, to: latest_end.max(time.from) },
+        Some(time) => time,
+    }
 }
 
-// Function to parse date and time from a string and return them as a tuple
-fn parse_date_and_time(date_and_time_str: &str) -> (String, String, String) {
-    let date_and_time_parts: Vec<&str> = date_and_time_str.split(',').collect();
-    let weekday = date_and_time_parts[0].trim().to_string();
+// Function to parse date and time from a string like "Mon, 04.09.23 08:00 - 16:30" and return them as a tuple
+fn parse_date_and_time(date_and_time_str: &str, source_url: &str) -> Result<(Weekday, NaiveDate, Option<TimeRange>), ParseError> {
+    let (weekday, date_and_time) = date_and_time_str.split_once(',')
+        .ok_or_else(|| ParseError::new("missing weekday", date_and_time_str, source_url))?;
 
-    let date_and_time = date_and_time_parts[1].trim();
-    let date_and_time_parts: Vec<&str> = date_and_time.split(' ').collect();
-    let date = date_and_time_parts[0].to_string();
-    let mut time = "".to_string();
+    let date_and_time_parts: Vec<&str> = date_and_time.split_whitespace().collect();
+    let date_str = date_and_time_parts.first().ok_or_else(|| ParseError::new("missing date", date_and_time_str, source_url))?;
 
-    if date_and_time_parts.len() >= 4 {
-        time = format!("{} - {}", date_and_time_parts[1], date_and_time_parts[3]);
-    }
+    let date = parse_date(date_str, ENTRY_DATE_FORMAT, source_url)?;
+    let weekday = parse_weekday(weekday, date, source_url)?;
+
+    let time = if date_and_time_parts.len() >= 4 {
+        let raw_time = date_and_time_parts[1..4].join(" ");
+        Some(TimeRange::parse(&raw_time).map_err(|e| ParseError::new(e, date_and_time_str, source_url))?)
+    } else {
+        None
+    };
 
-    return (weekday, date, time);
+    Ok((weekday, date, time))
 }
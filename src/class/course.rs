@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::error::Error;
 use log::{debug, error, info};
 use reqwest::{Client};
@@ -5,15 +6,14 @@ use scraper::{Html, Selector};
 use crate::{CONFIG};
 use crate::auth::client::get_body;
 use futures::{stream, StreamExt};
-use tokio::time::{sleep, Duration};
-use crate::class::classbook::extract_classbook;
+use crate::class::classbook::extract_classbooks;
 use std::sync::Arc;
 use crate::common::tables::{Classbook, Course};
 
 const COURSE_PATH: &str = "/course/view.php?id=";
 
 // Scrapes all courses
-pub async fn scrape_courses(client: Arc<Client>) -> Result<Vec<Course>, Box<dyn Error>> {
+pub async fn scrape_courses(client: Arc<Client>, settled: &HashSet<String>) -> Result<Vec<Course>, Box<dyn Error>> {
     let body = get_body(&*client, &format!("{}/", CONFIG.get_base_url())).await?;
     let mut courses = match extract_courses(&body) {
         Ok(courses) => courses,
@@ -28,22 +28,26 @@ pub async fn scrape_courses(client: Arc<Client>) -> Result<Vec<Course>, Box<dyn
         courses.truncate(1); // Only scrape the first course in test mode
     }
 
-    let max_concurrent_tasks = 100; // You can tune this number to the desired level of concurrency
+    // Settled courses are taken from the local store
+    courses.retain(|course| !settled.contains(&course.id));
+    info!("Scraping {} courses", courses.len());
+
+    let max_concurrent_tasks = CONFIG.get_max_in_flight().max(1); // The request rate itself is limited in get_body
     let temp_results: Vec<Result<Course, _>> = stream::iter(courses.into_iter().map(|course| {
         let client = Arc::clone(&client);
         let course_link = course.link.clone();
 
         async move {
-            sleep(Duration::from_millis(100)).await; // Add a small delay between requests to avoid overwhelming the server
-            let classbook = match scrape_classbook(client.clone(), &course_link).await {
-                Ok(classbook) => classbook,
+            let (classbooks, failed_classbooks) = match scrape_classbooks(client.clone(), &course_link).await {
+                Ok(classbooks) => classbooks,
                 Err(e) => {
-                    error!("Failed to scrape classbook: {}", e);
+                    error!("Failed to scrape the classbooks of course {}: {}", course.id, e);
                     return Err(e);
                 }
             };
             let mut course = course;
-            course.classbook = classbook;
+            course.classbooks = classbooks;
+            course.failed_classbooks = failed_classbooks;
 
             Ok(course)
         }
@@ -53,10 +57,10 @@ pub async fn scrape_courses(client: Arc<Client>) -> Result<Vec<Course>, Box<dyn
     results
 }
 
-// Scrapes the classbook for a course
-pub async fn scrape_classbook(client: Arc<Client>, course_link: &str) -> Result<Classbook, Box<dyn Error>> {
+// Scrapes all classbooks of a course, together with the IDs of the failed ones
+pub async fn scrape_classbooks(client: Arc<Client>, course_link: &str) -> Result<(Vec<Classbook>, Vec<String>), Box<dyn Error>> {
     let body = get_body(&*client, course_link).await?;
-    extract_classbook(&*client, &body).await
+    extract_classbooks(&*client, &body).await
 }
 
 // Extracts the courses from the body
@@ -73,31 +77,10 @@ pub fn extract_courses(body: &str) -> Result<Vec<Course>, Box<dyn Error>> {
             // Get the first element that matches the course name selector
             if let Some(course_name_element) = element.select(&course_name_selector).next() {
                 let course_name = course_name_element.text().collect::<String>().trim().to_string(); // Get the course name
-                let course_parts: Vec<&str> = course_name.split_whitespace().collect(); // Split the course name by whitespace
-                let len = course_parts.len();
 
-                if len < 2 || !course_parts[0].starts_with("LF") {
-                    continue; // Skip this course if it doesn't match the expected format or the course doesn't start with "LF"
+                if let Some(course) = parse_course(course_id, &course_name) {
+                    courses.push(course);
                 }
-
-                let course = String::from(course_parts[0]); // Get the first part of the course name
-                let duration = String::from(course_parts[len - 1]); // Get the last part of the course name
-                let name = course_parts[1..len - 1].join(" "); // Join the remaining parts of the course name
-                let link = generate_course_link(course_id); // Generate the course link
-
-                debug!("Course -> ID: {}, Name: {}, Lernfeld: {}, Duration: {}, Link: {}",
-                    course_id, name, course, duration, link);
-
-                let course = Course {
-                    id: course_id.to_string(),
-                    name,
-                    link,
-                    course,
-                    duration,
-                    classbook: Classbook { id: String::new(), link: String::new(), direct_link: Some(String::new()), entries: Vec::new() },
-                };
-
-                courses.push(course);
             }
         }
     }
@@ -105,6 +88,34 @@ pub fn extract_courses(body: &str) -> Result<Vec<Course>, Box<dyn Error>> {
     Ok(courses)
 }
 
+// Builds a course from its ID and its full name ("LFxx <Name> <Duration>")
+pub fn parse_course(course_id: &str, course_name: &str) -> Option<Course> {
+    let course_parts: Vec<&str> = course_name.split_whitespace().collect(); // Split the course name by whitespace
+    let len = course_parts.len();
+
+    if len < 2 || !course_parts[0].starts_with("LF") {
+        return None; // Skip this course if it doesn't match the expected format or the course doesn't start with "LF"
+    }
+
+    let course = String::from(course_parts[0]); // Get the first part of the course name
+    let duration = String::from(course_parts[len - 1]); // Get the last part of the course name
+    let name = course_parts[1..len - 1].join(" "); // Join the remaining parts of the course name
+    let link = generate_course_link(course_id); // Generate the course link
+
+    debug!("Course -> ID: {}, Name: {}, Lernfeld: {}, Duration: {}, Link: {}",
+        course_id, name, course, duration, link);
+
+    Some(Course {
+        id: course_id.to_string(),
+        name,
+        link,
+        course,
+        duration,
+        classbooks: Vec::new(),
+        failed_classbooks: Vec::new(),
+    })
+}
+
 // Generate the course link from the course ID
 pub fn generate_course_link(course_id: &str) -> String {
     format!("{}{}{}", CONFIG.get_base_url(), COURSE_PATH, course_id)
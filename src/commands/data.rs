@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::Arc;
+use log::{info, warn};
+use reqwest::Client;
+use crate::auth::client::create_client;
+use crate::auth::cookies::save_cookies;
+use crate::auth::fixtures::{set_fixture_mode, FixtureDir, FixtureMode};
+use crate::auth::login::login;
+use crate::auth::retry::is_auth_error;
+use crate::auth::session::probe_session;
+use crate::class::attendance::scrape_attendance;
+use crate::class::course::scrape_courses;
+use crate::common::tables::{Attendance, Course};
+use crate::export::import::import_dataset;
+use crate::store::database::Store;
+use crate::store::diff::detect_changes;
+use crate::utils::args::{FilterArgs, NetworkArgs, SourceArgs};
+use crate::utils::config::Backend;
+use crate::utils::exit::{Failure, FailureClass, OrFail};
+use crate::webservice::course::fetch_courses_and_attendance;
+use crate::CONFIG;
+
+pub type Data = (Vec<Course>, Vec<Attendance>);
+
+// Records or replays the HTTP traffic
+pub fn set_network_mode(network: &NetworkArgs) -> Result<(), Failure> {
+    let fixture_mode = match (&network.record, &network.replay) {
+        (Some(dir), _) => Some(FixtureMode::Record(FixtureDir::new(dir.clone()))),
+        (_, Some(dir)) => Some(FixtureMode::Replay(FixtureDir::new(dir.clone()))),
+        _ => None,
+    };
+    if let Some(mode) = fixture_mode {
+        set_fixture_mode(mode).or_fail(FailureClass::Data, "Failed to set up the fixtures")?;
+    }
+
+    Ok(())
+}
+
+// Reuses the stored session if it is still valid, otherwise logs in to the platform
+pub async fn ensure_session(client: &Client) -> Result<(), Failure> {
+    let session_valid = probe_session(client).await.unwrap_or_else(|e| {
+        warn!("Failed to probe the stored session: {}", e);
+        false
+    });
+
+    if session_valid {
+        info!("Stored session is still valid, skipping login");
+    } else {
+        info!("No valid stored session found, logging in");
+
+        login(client).await.or_fail(FailureClass::Auth, "Failed to login")?;
+
+        info!("Logged in successfully");
+    }
+
+    Ok(())
+}
+
+// Fetches the courses and the attendance from the configured backend, settled courses are skipped
+// The courses and classbooks that could not be fetched are returned with the data, see check_failures
+pub async fn fetch_data(settled: &HashSet<String>) -> Result<(Data, Vec<String>), Failure> {
+    // Create the client and cookie store
+    let (client, cookie_store) = create_client().await.or_fail(FailureClass::Auth, "Failed to create the client")?;
+
+    // Wrap the Client in an Arc
+    let client = Arc::new(client);
+
+    let (courses, attendances, mut failures) = match CONFIG.get_backend() {
+        Backend::Scrape => {
+            ensure_session(&client).await?;
+
+            info!("Read courses and create XLSX file...");
+
+            // Scrape the global attendance information
+            let attendances = scrape_attendance(Arc::clone(&client)).await.map_err(fetch_failure("Failed to scrape attendance"))?;
+
+            // Scrape the courses
+            let courses = scrape_courses(Arc::clone(&client), settled).await.map_err(fetch_failure("Failed to scrape courses"))?;
+            (courses, attendances, Vec::new())
+        }
+        Backend::WebService => {
+            info!("Using the Moodle web services backend");
+            info!("Read courses and create XLSX file...");
+
+            fetch_courses_and_attendance(Arc::clone(&client), settled).await.map_err(fetch_failure("Failed to fetch courses"))?
+        }
+    };
+
+    failures.extend(courses.iter().flat_map(|course| {
+        course.failed_classbooks.iter().map(move |id| format!("classbook {} of course {}", id, course.id))
+    }));
+
+    // Save the cookies
+    save_cookies(cookie_store).or_fail(FailureClass::Output, "Failed to save the cookies")?;
+
+    Ok(((courses, attendances), failures))
+}
+
+// A rejected token or relogin fails as an authentication failure, everything else of the fetch as a network failure
+fn fetch_failure(context: &str) -> impl FnOnce(Box<dyn Error>) -> Failure + '_ {
+    move |e| {
+        let class = if is_auth_error(&*e) { FailureClass::Auth } else { FailureClass::Network };
+        Failure::new(class, format!("{}: {}", context, e))
+    }
+}
+
+// Opens the local store if it is enabled (or forced, e.g. for the offline mode)
+pub fn open_store(force: bool) -> Result<Option<Store>, Failure> {
+    if CONFIG.is_store_enabled() || force {
+        Ok(Some(Store::open(CONFIG.get_store_path()).or_fail(FailureClass::Data, "Failed to open the local store")?))
+    } else {
+        Ok(None)
+    }
+}
+
+// Fetches the unsettled courses (all of them with `full`) into the store, reports what changed and returns the whole stored data
+// together with the courses and classbooks that could not be fetched, their stored data is kept
+pub async fn sync_store(store: &mut Store, full: bool) -> Result<(Data, Vec<String>), Failure> {
+    let settled = if full {
+        info!("Full sync, settled courses are fetched again");
+        HashSet::new()
+    } else {
+        store.settled_courses(CONFIG.get_refresh_days(), CONFIG.get_recheck_days()).or_fail(FailureClass::Data, "Failed to read the local store")?
+    };
+    let ((courses, attendances), failures) = fetch_data(&settled).await?;
+    let previous = load_from_store(store)?;
+
+    store.save_courses(&courses).or_fail(FailureClass::Data, "Failed to store the courses")?;
+    store.save_attendances(&attendances).or_fail(FailureClass::Data, "Failed to store the attendance")?;
+
+    let current = load_from_store(store)?;
+    report_changes(&previous, &current)?;
+
+    if !failures.is_empty() {
+        warn!("Kept the stored data of what could not be fetched: {}", failures.join(", "));
+    }
+
+    Ok((current, failures))
+}
+
+// Fails with a network failure if courses or classbooks could not be fetched in this run, called once the output was written
+pub fn check_failures(failures: &[String]) -> Result<(), Failure> {
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Failure::new(FailureClass::Network, format!("{} courses or classbooks could not be fetched: {}", failures.len(), failures.join(", "))))
+    }
+}
+
+// Loads the courses and the attendance from the local store
+pub fn load_from_store(store: &Store) -> Result<Data, Failure> {
+    let courses = store.load_courses().or_fail(FailureClass::Data, "Failed to load the courses from the local store")?;
+    let attendances = store.load_attendances().or_fail(FailureClass::Data, "Failed to load the attendance from the local store")?;
+
+    Ok((courses, attendances))
+}
+
+// Reads the data of render and export: the given dataset, otherwise the local store
+pub fn load_source(source: &SourceArgs) -> Result<Data, Failure> {
+    if let Some(path) = &source.input {
+        info!("Reading the dataset {}", path.display());
+        return import_dataset(path).or_fail(FailureClass::Data, "Failed to import the dataset");
+    }
+
+    let store = open_store(true)?.expect("the store is always opened when forced");
+    let last_sync = store.last_sync().or_fail(FailureClass::Data, "Failed to read the local store")?;
+
+    match last_sync {
+        Some(last_sync) => info!("Reading the local store {} (last sync: {})", CONFIG.get_store_path(), last_sync),
+        None => return Err(Failure::new(FailureClass::Data, format!("The local store {} is empty, run `moodle-report-portfolio scrape` first", CONFIG.get_store_path()))),
+    }
+
+    load_from_store(&store)
+}
+
+// Keeps the courses matching one of the course filters and the days in the date range
+pub fn filter_data(data: Data, filter: &FilterArgs) -> Data {
+    let (mut courses, mut attendances) = filter_courses(data, &filter.courses);
+    let range = filter.date_range();
+
+    for classbook in courses.iter_mut().flat_map(|course| course.classbooks.iter_mut()) {
+        classbook.entries.retain(|entry| range.contains(entry.date));
+    }
+    attendances.retain(|attendance| range.contains(attendance.date));
+
+    (courses, attendances)
+}
+
+// Keeps the courses whose name, id or course number contains one of the patterns (case-insensitive), all without patterns
+pub fn filter_courses(data: Data, patterns: &[String]) -> Data {
+    let (mut courses, attendances) = data;
+
+    if !patterns.is_empty() {
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_lowercase()).collect();
+        courses.retain(|course| {
+            let fields = [course.name.to_lowercase(), course.id.to_lowercase(), course.course.to_lowercase()];
+            patterns.iter().any(|pattern| fields.iter().any(|field| field.contains(pattern.as_str())))
+        });
+
+        if courses.is_empty() {
+            warn!("No course matches {}", patterns.join(", "));
+        }
+    }
+
+    (courses, attendances)
+}
+
+// Compares the previous snapshot of the store with the current one and writes the change report
+fn report_changes(previous: &Data, current: &Data) -> Result<(), Failure> {
+    if previous.0.is_empty() {
+        info!("No previous snapshot in the local store, skipping change detection");
+        return Ok(());
+    }
+
+    let report = detect_changes(&previous.0, &previous.1, &current.0, &current.1).or_fail(FailureClass::Data, "Failed to detect changes")?;
+
+    if report.is_empty() {
+        info!("No changes since the last run");
+        return Ok(());
+    }
+
+    let generated_at = chrono::Local::now().format("%Y-%m-%d %H:%M").to_string();
+    std::fs::write(CONFIG.get_changes_report(), report.to_markdown(&generated_at))
+        .or_fail(FailureClass::Output, "Failed to write the change report")?;
+
+    warn!("{} classbook days and {} attendance records changed since the last run, see {}",
+        report.days.len(), report.attendances.len(), CONFIG.get_changes_report());
+    for week in &report.weeks {
+        warn!("Affected report week: {}", week);
+    }
+
+    Ok(())
+}
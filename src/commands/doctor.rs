@@ -0,0 +1,195 @@
+use std::path::Path;
+use crate::auth::client::create_client;
+use crate::auth::cookies::COOKIE_STORE_PATH;
+use crate::auth::secret::check_permissions;
+use crate::auth::session::probe_session;
+use crate::common::tables::SiteInfo;
+use crate::overlay::merge::load_overlay;
+use crate::store::database::{Store, SCHEMA_VERSION};
+use crate::utils::config::Backend;
+use crate::utils::exit::{Failure, FailureClass};
+use crate::webservice::rest::call_function;
+use crate::CONFIG;
+
+// Outcome of a single check, a failure carries the class that decides the exit code
+enum Status {
+    Ok,
+    Warning,
+    Failed(FailureClass),
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Check {
+        Check { name, status, detail: detail.into() }
+    }
+}
+
+// Checks the setup without changing anything but the session, the exit code is the one of the first failed check
+pub async fn run_doctor() -> Result<(), Failure> {
+    let checks = vec![
+        Check::new("configuration", Status::Ok, CONFIG.get_config_path().display().to_string()),
+        check_password_source(),
+        check_session_files(),
+        check_store(),
+        check_overlay(),
+        check_platform().await,
+    ];
+
+    for check in &checks {
+        let label = match check.status {
+            Status::Ok => "ok",
+            Status::Warning => "warn",
+            Status::Failed(_) => "FAIL",
+        };
+        println!("[{:<4}] {:<14} {}", label, check.name, check.detail);
+    }
+
+    let failed: Vec<FailureClass> = checks.iter().filter_map(|check| match check.status {
+        Status::Failed(class) => Some(class),
+        _ => None,
+    }).collect();
+
+    match failed.first() {
+        Some(class) => Err(Failure::new(*class, format!("{} of {} checks failed", failed.len(), checks.len()))),
+        None => Ok(()),
+    }
+}
+
+// Only checks that the configured source is usable, a password command is not run
+fn check_password_source() -> Check {
+    let name = "password";
+
+    if CONFIG.get_backend() == Backend::WebService {
+        return Check::new(name, Status::Ok, "not needed for the webservice backend");
+    }
+
+    if !CONFIG.get_password().is_empty() {
+        return Check::new(name, Status::Warning, "stored in plain text in the configuration, consider password_env, password_command or password_file");
+    }
+
+    let variable = CONFIG.get_password_env();
+    if !variable.is_empty() {
+        return match std::env::var(variable) {
+            Ok(value) if !value.is_empty() => Check::new(name, Status::Ok, format!("from ${}", variable)),
+            _ => Check::new(name, Status::Failed(FailureClass::Auth), format!("${} is not set", variable)),
+        };
+    }
+
+    if !CONFIG.get_password_command().is_empty() {
+        return Check::new(name, Status::Ok, "from the password command (not run)");
+    }
+
+    let file = CONFIG.get_password_file();
+    if !file.is_empty() {
+        return match check_permissions(Path::new(file)) {
+            Ok(()) => Check::new(name, Status::Ok, format!("from {}", file)),
+            Err(e) => Check::new(name, Status::Failed(FailureClass::Auth), e.to_string()),
+        };
+    }
+
+    if CONFIG.is_vault_enabled() {
+        Check::new(name, Status::Ok, "from the vault, otherwise asked for at the login")
+    } else {
+        Check::new(name, Status::Ok, "asked for at the login")
+    }
+}
+
+// The session is either in the vault or in cookies.json, both must only be readable by the current user
+fn check_session_files() -> Check {
+    let name = "session";
+    let cookies = Path::new(COOKIE_STORE_PATH);
+
+    if CONFIG.is_vault_enabled() {
+        let vault = Path::new(CONFIG.get_vault_path());
+
+        if cookies.exists() {
+            return Check::new(name, Status::Warning, format!("{} still holds a session in plain text next to the vault", COOKIE_STORE_PATH));
+        }
+        if !vault.exists() {
+            return Check::new(name, Status::Warning, format!("the vault {} is created at the first login", vault.display()));
+        }
+
+        return match check_permissions(vault) {
+            Ok(()) => Check::new(name, Status::Ok, format!("in the vault {}", vault.display())),
+            Err(e) => Check::new(name, Status::Warning, e.to_string()),
+        };
+    }
+
+    if !cookies.exists() {
+        return Check::new(name, Status::Ok, "no stored session yet");
+    }
+
+    match check_permissions(cookies) {
+        Ok(()) => Check::new(name, Status::Ok, format!("in {}", COOKIE_STORE_PATH)),
+        Err(e) => Check::new(name, Status::Warning, e.to_string()),
+    }
+}
+
+fn check_store() -> Check {
+    let name = "local store";
+    let path = CONFIG.get_store_path();
+
+    if !CONFIG.is_store_enabled() {
+        return Check::new(name, Status::Ok, "disabled");
+    }
+    if !Path::new(path).exists() {
+        return Check::new(name, Status::Warning, format!("{} does not exist yet, run `moodle-report-portfolio scrape`", path));
+    }
+
+    // Opened read-only, the doctor must not create the store
+    let store = match Store::open_read_only(path).and_then(|store| Ok((store.schema_version()?, store))) {
+        Ok((version, _)) if version > SCHEMA_VERSION => {
+            return Check::new(name, Status::Failed(FailureClass::Data), format!("{} has schema version {}, this version only reads up to {}", path, version, SCHEMA_VERSION));
+        }
+        Ok((0, _)) => {
+            return Check::new(name, Status::Warning, format!("{} has no tables yet, run `moodle-report-portfolio scrape`", path));
+        }
+        Ok((_, store)) => store,
+        Err(e) => return Check::new(name, Status::Failed(FailureClass::Data), format!("{}: {}", path, e)),
+    };
+
+    match store.last_sync() {
+        Ok(Some(last_sync)) => Check::new(name, Status::Ok, format!("{} (last sync: {})", path, last_sync)),
+        Ok(None) => Check::new(name, Status::Warning, format!("{} is empty, run `moodle-report-portfolio scrape`", path)),
+        Err(e) => Check::new(name, Status::Failed(FailureClass::Data), format!("{}: {}", path, e)),
+    }
+}
+
+fn check_overlay() -> Check {
+    let name = "overlay";
+
+    match load_overlay(CONFIG.get_overlay_path()) {
+        Ok(Some(overlay)) => Check::new(name, Status::Ok, format!("{} entries in {}", overlay.entries.len(), CONFIG.get_overlay_path())),
+        Ok(None) => Check::new(name, Status::Ok, "no overlay file"),
+        Err(e) => Check::new(name, Status::Failed(FailureClass::Data), e.to_string()),
+    }
+}
+
+// Reaches the platform with the stored session or the web service token
+async fn check_platform() -> Check {
+    let name = "platform";
+
+    let client = match create_client().await {
+        Ok((client, _)) => client,
+        Err(e) => return Check::new(name, Status::Failed(FailureClass::Auth), format!("failed to create the client: {}", e)),
+    };
+
+    match CONFIG.get_backend() {
+        Backend::Scrape => match probe_session(&client).await {
+            Ok(true) => Check::new(name, Status::Ok, format!("{} reachable, the stored session is valid", CONFIG.get_base_url())),
+            Ok(false) => Check::new(name, Status::Warning, format!("{} reachable, no valid session, run `moodle-report-portfolio login`", CONFIG.get_base_url())),
+            Err(e) => Check::new(name, Status::Failed(FailureClass::Network), e.to_string()),
+        },
+        Backend::WebService => match call_function::<SiteInfo>(&client, "core_webservice_get_site_info", &[]).await {
+            Ok(site_info) => Check::new(name, Status::Ok, format!("{} reachable, the token belongs to user id {}", CONFIG.get_base_url(), site_info.userid)),
+            // The request URL in the error contains the token
+            Err(e) => Check::new(name, Status::Failed(FailureClass::Network), e.to_string().replace(CONFIG.get_token(), "<token>")),
+        },
+    }
+}
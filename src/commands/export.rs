@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+use crate::commands::data::{filter_data, load_source};
+use crate::export::dataset::{export_dataset, ExportFormat};
+use crate::utils::args::{FilterArgs, SourceArgs};
+use crate::utils::exit::{Failure, FailureClass, OrFail};
+
+// Exports the filtered data of a dataset or the local store
+pub fn run_export(format: ExportFormat, source: &SourceArgs, filter: &FilterArgs, output: Option<PathBuf>) -> Result<(), Failure> {
+    let (courses, attendances) = filter_data(load_source(source)?, filter);
+
+    let path = output.unwrap_or_else(|| PathBuf::from(format!("Reports.{}", format.extension())));
+    export_dataset(&courses, &attendances, format, &path).or_fail(FailureClass::Output, "Failed to export the data")
+}
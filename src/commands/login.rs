@@ -0,0 +1,28 @@
+use log::info;
+use crate::auth::client::create_client;
+use crate::auth::cookies::save_cookies;
+use crate::commands::data::{ensure_session, set_network_mode};
+use crate::common::tables::SiteInfo;
+use crate::utils::args::NetworkArgs;
+use crate::utils::config::Backend;
+use crate::utils::exit::{Failure, FailureClass, OrFail};
+use crate::webservice::rest::call_function;
+use crate::CONFIG;
+
+// Establishes a session and keeps it for the next runs, the web services backend only checks its token
+pub async fn run_login(network: &NetworkArgs) -> Result<(), Failure> {
+    set_network_mode(network)?;
+
+    let (client, cookie_store) = create_client().await.or_fail(FailureClass::Auth, "Failed to create the client")?;
+
+    match CONFIG.get_backend() {
+        Backend::Scrape => ensure_session(&client).await?,
+        Backend::WebService => {
+            let site_info: SiteInfo = call_function(&client, "core_webservice_get_site_info", &[]).await
+                .or_fail(FailureClass::Auth, "Failed to check the web service token")?;
+            info!("The web service token is valid (user id {})", site_info.userid);
+        }
+    }
+
+    save_cookies(cookie_store).or_fail(FailureClass::Output, "Failed to save the cookies")
+}
@@ -0,0 +1,7 @@
+pub mod data;
+pub mod doctor;
+pub mod export;
+pub mod login;
+pub mod render;
+pub mod scrape;
+pub mod vault;
\ No newline at end of file
@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+use crate::commands::data::{filter_courses, load_source};
+use crate::excel::process::process_course;
+use crate::utils::args::{FilterArgs, SourceArgs};
+use crate::utils::exit::{Failure, FailureClass, OrFail};
+
+const DEFAULT_REPORT_PATH: &str = "Reports.xlsx";
+
+// Creates the report book without any network access, the date range selects the weeks but keeps their numbers
+pub async fn run_render(source: &SourceArgs, filter: &FilterArgs, output: Option<PathBuf>) -> Result<(), Failure> {
+    let (courses, attendances) = filter_courses(load_source(source)?, &filter.courses);
+
+    let path = output.unwrap_or_else(|| PathBuf::from(DEFAULT_REPORT_PATH));
+    process_course(&courses, &attendances, &path, filter.date_range()).await.or_fail(FailureClass::Output, "Failed to process courses")
+}
@@ -0,0 +1,25 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use crate::commands::data::{check_failures, fetch_data, filter_data, open_store, set_network_mode, sync_store};
+use crate::export::dataset::{export_dataset, ExportFormat};
+use crate::utils::args::{FilterArgs, NetworkArgs};
+use crate::utils::exit::{Failure, FailureClass, OrFail};
+
+const DEFAULT_DATASET_PATH: &str = "dataset.json";
+
+// Fetches the data into the local store (if it is enabled) and writes the filtered data as a JSON dataset
+// The filters only narrow the dataset down, the fetch and the sync always cover all courses so the store stays complete
+pub async fn run_scrape(network: &NetworkArgs, filter: &FilterArgs, output: Option<PathBuf>, full: bool) -> Result<(), Failure> {
+    set_network_mode(network)?;
+
+    let (data, failures) = match open_store(false)? {
+        Some(mut store) => sync_store(&mut store, full).await?,
+        None => fetch_data(&HashSet::new()).await?,
+    };
+    let (courses, attendances) = filter_data(data, filter);
+
+    let path = output.unwrap_or_else(|| PathBuf::from(DEFAULT_DATASET_PATH));
+    export_dataset(&courses, &attendances, ExportFormat::Json, &path).or_fail(FailureClass::Output, "Failed to write the dataset")?;
+
+    check_failures(&failures)
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+use log::info;
+use zeroize::{Zeroize, Zeroizing};
+use crate::auth::vault::{prompt_passphrase, read_passphrase, wipe_vault, with_vault, Vault};
+use crate::utils::args::VaultAction;
+use crate::utils::exit::{Failure, FailureClass, OrFail};
+use crate::CONFIG;
+
+// Maintains the vault of the configuration
+pub fn run_vault(action: &VaultAction) -> Result<(), Failure> {
+    let path = Path::new(CONFIG.get_vault_path());
+
+    match action {
+        VaultAction::Rotate => {
+            if !path.exists() {
+                return Err(Failure::new(FailureClass::Data, format!("There is no vault at {}", path.display())));
+            }
+
+            let passphrase = read_passphrase("Current vault passphrase: ", false).or_fail(FailureClass::Auth, "Failed to read the passphrase")?;
+            let mut vault = Vault::open(path, &passphrase).or_fail(FailureClass::Auth, "Failed to open the vault")?;
+            let passphrase = prompt_passphrase("New vault passphrase: ", true).or_fail(FailureClass::Auth, "Failed to read the new passphrase")?;
+
+            vault.rekey(&passphrase).and_then(|_| vault.save()).or_fail(FailureClass::Output, "Failed to rotate the vault")?;
+            info!("Re-encrypted the vault {} with the new passphrase", path.display());
+        }
+        VaultAction::Wipe => {
+            if wipe_vault(path).or_fail(FailureClass::Output, "Failed to wipe the vault")? {
+                info!("Wiped the vault {}", path.display());
+            } else {
+                info!("There is no vault at {}", path.display());
+            }
+        }
+        VaultAction::SetPassword => {
+            if !CONFIG.is_vault_enabled() {
+                return Err(Failure::new(FailureClass::Config, "The vault is disabled, enable it in the [vault] section first"));
+            }
+
+            // Unlocked first, the vault is not locked while the password is asked for
+            with_vault(|_| Ok(())).or_fail(FailureClass::Auth, "Failed to unlock the vault")?;
+            let password = Zeroizing::new(rpassword::prompt_password(format!("Password for {}: ", CONFIG.get_user_name()))
+                .or_fail(FailureClass::Auth, "Failed to read the password")?);
+
+            with_vault(|vault| {
+                if let Some(previous) = vault.contents.password.as_mut() {
+                    previous.zeroize();
+                }
+                vault.contents.password = if password.is_empty() { None } else { Some(password.to_string()) };
+                vault.save()
+            }).or_fail(FailureClass::Output, "Failed to store the password")?;
+            info!("Updated the password in the vault {}", path.display());
+        }
+    }
+
+    Ok(())
+}
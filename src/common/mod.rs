@@ -1 +1,2 @@
-pub mod tables;
\ No newline at end of file
+pub mod parse;
+pub mod tables;
@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use crate::common::tables::{AttendanceStatus, TimeRange};
+
+// Date formats of the platform, the classbook uses two-digit years and the attendance page four-digit years
+pub const ENTRY_DATE_FORMAT: &str = "%d.%m.%y";
+pub const ATTENDANCE_DATE_FORMAT: &str = "%d.%m.%Y";
+pub const TIME_FORMAT: &str = "%H:%M";
+
+// A row of the platform that could not be turned into the typed model
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub raw: String,
+    pub source_url: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: \"{}\" (source: {})", self.message, self.raw, self.source_url)
+    }
+}
+
+impl Error for ParseError {}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>, raw: &str, source_url: &str) -> ParseError {
+        ParseError { message: message.into(), raw: raw.to_string(), source_url: source_url.to_string() }
+    }
+}
+
+// Parses a date in the given format
+pub fn parse_date(raw: &str, format: &str, source_url: &str) -> Result<NaiveDate, ParseError> {
+    NaiveDate::parse_from_str(raw.trim(), format)
+        .map_err(|e| ParseError::new(format!("invalid date ({})", e), raw, source_url))
+}
+
+// Parses an English weekday ("Mon", "Monday") and checks that it matches the date
+pub fn parse_weekday(raw: &str, date: NaiveDate, source_url: &str) -> Result<Weekday, ParseError> {
+    let weekday = Weekday::from_str(raw.trim()).map_err(|_| ParseError::new("invalid weekday", raw, source_url))?;
+
+    if weekday != date.weekday() {
+        return Err(ParseError::new(format!("weekday does not match the date {} ({})", date.format(ENTRY_DATE_FORMAT), date.weekday()), raw, source_url));
+    }
+
+    Ok(weekday)
+}
+
+// Parses a time like "08:00"
+pub fn parse_time(raw: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(raw.trim(), TIME_FORMAT).ok()
+}
+
+impl TimeRange {
+    // Parses a time range like "08:00 - 16:30"
+    pub fn parse(raw: &str) -> Result<TimeRange, String> {
+        let (from, to) = raw.split_once('-').ok_or_else(|| format!("invalid time range \"{}\", expected HH:MM - HH:MM", raw))?;
+        let from = parse_time(from).ok_or_else(|| format!("invalid start time in \"{}\"", raw))?;
+        let to = parse_time(to).ok_or_else(|| format!("invalid end time in \"{}\"", raw))?;
+
+        if to < from {
+            return Err(format!("the time range \"{}\" ends before it starts", raw));
+        }
+
+        Ok(TimeRange { from, to })
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} - {}", self.from.format(TIME_FORMAT), self.to.format(TIME_FORMAT))
+    }
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        TimeRange::parse(&raw)
+    }
+}
+
+impl From<TimeRange> for String {
+    fn from(range: TimeRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl AttendanceStatus {
+    // Parses the status column of the attendance page or a status of the attendance module,
+    // either the German or English description or the acronym (P, L/V, E, A)
+    pub fn parse(raw: &str) -> Option<AttendanceStatus> {
+        let status = raw.trim().to_lowercase();
+
+        // The qualifier decides, so "unentschuldigt" is checked before "entschuldigt" and both before "abwesend",
+        // e.g. "Entschuldigt abwesend" is excused
+        if status.contains("krank") || status.contains("sick") {
+            Some(AttendanceStatus::Sick)
+        } else if status.contains("urlaub") || status.contains("vacation") {
+            Some(AttendanceStatus::Vacation)
+        } else if status.contains("unentschuldigt") || status.contains("unexcused") {
+            Some(AttendanceStatus::Absent)
+        } else if status.contains("entschuldigt") || status.contains("excused") || status == "e" {
+            Some(AttendanceStatus::Excused)
+        } else if status.contains("abwesend") || status.contains("absent") || status == "a" {
+            Some(AttendanceStatus::Absent)
+        } else if status.contains("verspätet") || status.contains("late") || status == "l" || status == "v" {
+            Some(AttendanceStatus::Late)
+        } else if status.contains("anwesend") || status.contains("present") || status == "p" {
+            Some(AttendanceStatus::Present)
+        } else {
+            None
+        }
+    }
+
+    // Check if the trainee missed the day
+    pub fn is_absence(&self) -> bool {
+        !matches!(self, AttendanceStatus::Present | AttendanceStatus::Late)
+    }
+}
+
+impl fmt::Display for AttendanceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            AttendanceStatus::Present => "present",
+            AttendanceStatus::Late => "late",
+            AttendanceStatus::Sick => "sick",
+            AttendanceStatus::Excused => "excused",
+            AttendanceStatus::Vacation => "vacation",
+            AttendanceStatus::Absent => "absent",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+impl FromStr for AttendanceStatus {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        AttendanceStatus::parse(raw).ok_or_else(|| format!("unknown attendance status \"{}\"", raw))
+    }
+}
+
+// Parses a duration in minutes, given as "7:30" (hours and minutes), "7,5 Std." (hours) or "450 min"
+pub fn parse_duration(raw: &str) -> Option<u32> {
+    let raw = raw.trim().to_lowercase();
+
+    if let Some((hours, minutes)) = raw.split_once(':') {
+        let hours: u32 = hours.trim().parse().ok()?;
+        let minutes: u32 = minutes.trim().trim_end_matches(|c: char| !c.is_ascii_digit()).parse().ok()?;
+        return Some(hours * 60 + minutes);
+    }
+
+    let number: String = raw.chars().take_while(|c| c.is_ascii_digit() || *c == ',' || *c == '.').collect();
+    let value: f64 = number.replace(',', ".").parse().ok()?;
+
+    if raw.contains("min") {
+        Some(value.round() as u32)
+    } else {
+        Some((value * 60.0).round() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_german_status_labels() {
+        assert_eq!(AttendanceStatus::parse("Anwesend"), Some(AttendanceStatus::Present));
+        assert_eq!(AttendanceStatus::parse("Verspätet"), Some(AttendanceStatus::Late));
+        assert_eq!(AttendanceStatus::parse("Krank"), Some(AttendanceStatus::Sick));
+        assert_eq!(AttendanceStatus::parse("Urlaub"), Some(AttendanceStatus::Vacation));
+        assert_eq!(AttendanceStatus::parse("Entschuldigt"), Some(AttendanceStatus::Excused));
+        assert_eq!(AttendanceStatus::parse("Entschuldigt abwesend"), Some(AttendanceStatus::Excused));
+        assert_eq!(AttendanceStatus::parse("Abwesend (entschuldigt)"), Some(AttendanceStatus::Excused));
+        assert_eq!(AttendanceStatus::parse("Unentschuldigt"), Some(AttendanceStatus::Absent));
+        assert_eq!(AttendanceStatus::parse("Unentschuldigt abwesend"), Some(AttendanceStatus::Absent));
+        assert_eq!(AttendanceStatus::parse("Abwesend"), Some(AttendanceStatus::Absent));
+    }
+
+    #[test]
+    fn parses_english_status_labels() {
+        assert_eq!(AttendanceStatus::parse("Present"), Some(AttendanceStatus::Present));
+        assert_eq!(AttendanceStatus::parse("Late"), Some(AttendanceStatus::Late));
+        assert_eq!(AttendanceStatus::parse("Sick"), Some(AttendanceStatus::Sick));
+        assert_eq!(AttendanceStatus::parse("Vacation"), Some(AttendanceStatus::Vacation));
+        assert_eq!(AttendanceStatus::parse("Excused"), Some(AttendanceStatus::Excused));
+        assert_eq!(AttendanceStatus::parse("Excused absence"), Some(AttendanceStatus::Excused));
+        assert_eq!(AttendanceStatus::parse("Unexcused absence"), Some(AttendanceStatus::Absent));
+        assert_eq!(AttendanceStatus::parse("Absent"), Some(AttendanceStatus::Absent));
+    }
+
+    #[test]
+    fn parses_status_acronyms() {
+        assert_eq!(AttendanceStatus::parse(" P "), Some(AttendanceStatus::Present));
+        assert_eq!(AttendanceStatus::parse("L"), Some(AttendanceStatus::Late));
+        assert_eq!(AttendanceStatus::parse("V"), Some(AttendanceStatus::Late));
+        assert_eq!(AttendanceStatus::parse("E"), Some(AttendanceStatus::Excused));
+        assert_eq!(AttendanceStatus::parse("A"), Some(AttendanceStatus::Absent));
+        assert_eq!(AttendanceStatus::parse(""), None);
+        assert_eq!(AttendanceStatus::parse("?"), None);
+    }
+}
@@ -1,6 +1,9 @@
+use chrono::{NaiveDate, NaiveTime, Weekday};
 use linked_hash_set::LinkedHashSet;
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use toml::value::Datetime;
 use xlsxwriter::format::FormatBorder;
+use zeroize::Zeroize;
 
 #[derive(Serialize)]
 pub struct LoginForm {
@@ -9,7 +12,13 @@ pub struct LoginForm {
     pub logintoken: String,
 }
 
-#[derive(Clone)]
+impl Drop for LoginForm {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Classbook {
     pub id: String,
     pub link: String,
@@ -17,30 +26,130 @@ pub struct Classbook {
     pub entries: Vec<ClassbookEntry>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ClassbookEntry {
-    pub weekday: String,
-    pub date: String,
-    pub time: String,
+    pub weekday: Weekday,
+    pub date: NaiveDate,
+    pub time: Option<TimeRange>,
     pub description: String,
     pub activities: LinkedHashSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hours: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub learning_field: Option<String>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Course {
     pub id: String,
     pub name: String,
     pub link: String,
     pub course: String,
     pub duration: String,
-    pub classbook: Classbook,
+    pub classbooks: Vec<Classbook>,
+    // Classbooks that could not be fetched in this run, their stored entries are kept
+    #[serde(skip)]
+    pub failed_classbooks: Vec<String>,
 }
 
-#[derive(Clone)]
+// Range of days given on the command line, open on either end
+#[derive(Clone, Copy, Default)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from.map_or(true, |from| date >= from) && self.to.map_or(true, |to| date <= to)
+    }
+}
+
+// Time range of a session or an attendance, written as "08:00 - 16:30"
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeRange {
+    pub from: NaiveTime,
+    pub to: NaiveTime,
+}
+
+// An attendance record, the time is missing if the trainee was absent
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Attendance {
-    pub date: String,
-    pub from_time: String,
-    pub to_time: String,
+    pub date: NaiveDate,
+    pub time: Option<TimeRange>,
+    pub status: AttendanceStatus,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub remarks: String,
+    // Attended minutes as stated by the attendance page or the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+}
+
+// Status of an attendance record
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttendanceStatus {
+    Present,
+    Late,
+    Sick,
+    Excused,
+    Vacation,
+    Absent,
+}
+
+// Versioned dataset of the scraped model, used by the export
+#[derive(Serialize, Deserialize)]
+pub struct Dataset {
+    pub schema_version: u32,
+    pub generated_at: String,
+    pub courses: Vec<Course>,
+    pub attendances: Vec<Attendance>,
+}
+
+// Overlay file with manual entries (company weeks, vacation, corrections)
+#[derive(Deserialize)]
+pub struct OverlayFile {
+    #[serde(default, rename = "entry")]
+    pub entries: Vec<OverlayEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct OverlayEntry {
+    pub date: Option<Datetime>,
+    pub from: Option<Datetime>,
+    pub to: Option<Datetime>,
+    #[serde(default)]
+    pub kind: OverlayKind,
+    #[serde(default)]
+    pub policy: MergePolicy,
+    pub time: Option<TimeRange>,
+    #[serde(default)]
+    pub activities: Vec<String>,
+    pub hours: Option<f64>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlayKind {
+    School,
+    Company,
+    Vacation,
+    #[default]
+    Other,
+}
+
+// How an overlay entry is merged with the scraped entries of the same day
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergePolicy {
+    // The overlay entry replaces the scraped entries
+    #[default]
+    Replace,
+    // The activities are appended to the scraped day, after all of its sessions
+    Append,
+    // The overlay entry is only used if there is no scraped entry
+    Add,
 }
 
 #[derive(Clone)]
@@ -54,3 +163,62 @@ pub struct Cell {
     pub bold: bool,
     pub border_top: FormatBorder,
 }
+
+// Moodle web services: core_webservice_get_site_info
+#[derive(Deserialize)]
+pub struct SiteInfo {
+    pub userid: u64,
+}
+
+// Moodle web services: core_enrol_get_users_courses
+#[derive(Deserialize)]
+pub struct EnrolledCourse {
+    pub id: u64,
+    pub fullname: String,
+}
+
+// Moodle web services: core_course_get_contents
+#[derive(Deserialize)]
+pub struct CourseSection {
+    #[serde(default)]
+    pub modules: Vec<CourseModule>,
+}
+
+#[derive(Deserialize)]
+pub struct CourseModule {
+    pub id: u64,
+    pub name: String,
+    pub modname: String,
+    #[serde(default)]
+    pub instance: u64,
+    #[serde(default)]
+    pub url: String,
+}
+
+// Moodle web services: mod_attendance_get_sessions
+#[derive(Deserialize)]
+pub struct AttendanceSession {
+    pub sessdate: i64,
+    pub duration: i64,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub statuses: Vec<AttendanceSessionStatus>,
+    #[serde(default)]
+    pub attendance_log: Vec<AttendanceLog>,
+}
+
+#[derive(Deserialize)]
+pub struct AttendanceSessionStatus {
+    pub id: u64,
+    pub acronym: String,
+    pub description: String,
+}
+
+#[derive(Deserialize)]
+pub struct AttendanceLog {
+    pub studentid: u64,
+    pub statusid: u64,
+    #[serde(default)]
+    pub remarks: String,
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use chrono::NaiveDate;
+use linked_hash_set::LinkedHashSet;
+use crate::common::tables::{ClassbookEntry, TimeRange};
+
+// The session time ranges of every date, used to compute the hours of the merged days
+pub fn sessions_by_date(entries: &[ClassbookEntry]) -> HashMap<NaiveDate, Vec<TimeRange>> {
+    let mut sessions: HashMap<NaiveDate, Vec<TimeRange>> = HashMap::new();
+
+    for entry in entries {
+        if let Some(time) = entry.time {
+            sessions.entry(entry.date).or_default().push(time);
+        }
+    }
+
+    sessions
+}
+
+// Merges the sorted entries of the same date (several sessions or courses on one day) into a single day block,
+// the activities are deduplicated in order and optionally grouped by their Lernfeld
+pub fn merge_days(entries: &[ClassbookEntry], group_by_learning_field: bool) -> Vec<ClassbookEntry> {
+    let mut days: Vec<ClassbookEntry> = Vec::new();
+    let mut start = 0;
+
+    while start < entries.len() {
+        let date = entries[start].date;
+        let end = entries[start..].iter().position(|entry| entry.date != date).map_or(entries.len(), |len| start + len);
+
+        days.push(merge_day(&entries[start..end], group_by_learning_field));
+        start = end;
+    }
+
+    days
+}
+
+fn merge_day(entries: &[ClassbookEntry], group_by_learning_field: bool) -> ClassbookEntry {
+    let first = &entries[0];
+    if entries.len() == 1 && !group_by_learning_field {
+        return first.clone();
+    }
+
+    // The time of the day block spans all sessions
+    let times: Vec<TimeRange> = entries.iter().filter_map(|entry| entry.time).collect();
+    let time = times.iter().map(|time| time.from).min()
+        .zip(times.iter().map(|time| time.to).max())
+        .map(|(from, to)| TimeRange { from, to });
+
+    let descriptions: LinkedHashSet<&str> = entries.iter()
+        .map(|entry| entry.description.as_str())
+        .filter(|description| !description.is_empty())
+        .collect();
+
+    let activities = if group_by_learning_field {
+        grouped_activities(entries)
+    } else {
+        // insert would move a repeated activity to the end, the order of the first mention is kept
+        let mut activities = LinkedHashSet::new();
+        for activity in entries.iter().flat_map(|entry| &entry.activities) {
+            activities.insert_if_absent(activity.clone());
+        }
+        activities
+    };
+
+    // A single learning field is kept, mixed days have none
+    let learning_fields: LinkedHashSet<&String> = entries.iter().filter_map(|entry| entry.learning_field.as_ref()).collect();
+    let learning_field = match learning_fields.len() {
+        1 => learning_fields.into_iter().next().cloned(),
+        _ => None,
+    };
+
+    ClassbookEntry {
+        weekday: first.weekday,
+        date: first.date,
+        time,
+        description: descriptions.into_iter().collect::<Vec<_>>().join("\n"),
+        activities,
+        // Hours set by the overlay or the holiday calendar are kept
+        hours: entries.iter().find_map(|entry| entry.hours),
+        learning_field,
+    }
+}
+
+// One activity per learning field, e.g. "LF08 (Joins, Normalformen)", entries without a learning field stay as they are
+fn grouped_activities(entries: &[ClassbookEntry]) -> LinkedHashSet<String> {
+    let mut groups: Vec<(Option<&String>, LinkedHashSet<String>)> = Vec::new();
+
+    for entry in entries {
+        let key = entry.learning_field.as_ref();
+        let index = match groups.iter().position(|(field, _)| *field == key) {
+            Some(index) => index,
+            None => {
+                groups.push((key, LinkedHashSet::new()));
+                groups.len() - 1
+            }
+        };
+
+        for activity in &entry.activities {
+            groups[index].1.insert_if_absent(activity.clone());
+        }
+    }
+
+    let mut activities = LinkedHashSet::new();
+
+    for (field, group) in groups {
+        match field {
+            Some(field) if !group.is_empty() => {
+                activities.insert(format!("{} ({})", field, group.into_iter().collect::<Vec<_>>().join(", ")));
+            }
+            _ => {
+                for activity in group {
+                    activities.insert_if_absent(activity);
+                }
+            }
+        }
+    }
+
+    activities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+
+    fn entry(date: &str, time: &str, learning_field: Option<&str>, activities: &[&str]) -> ClassbookEntry {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+
+        ClassbookEntry {
+            weekday: date.weekday(),
+            date,
+            time: Some(TimeRange::parse(time).unwrap()),
+            description: activities.join(", "),
+            activities: activities.iter().map(|activity| activity.to_string()).collect(),
+            hours: None,
+            learning_field: learning_field.map(String::from),
+        }
+    }
+
+    fn activities(entry: &ClassbookEntry) -> Vec<&str> {
+        entry.activities.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn merges_the_sessions_of_a_day() {
+        let entries = [
+            entry("2024-09-02", "08:30 - 12:00", Some("LF05"), &["SQL", "Joins"]),
+            entry("2024-09-02", "11:00 - 16:00", Some("LF05"), &["Views", "SQL"]),
+            entry("2024-09-03", "08:30 - 12:00", None, &["Git"]),
+        ];
+
+        let days = merge_days(&entries, false);
+
+        assert_eq!(days.len(), 2);
+        // The overlapping sessions span the whole block, duplicate activities and descriptions are kept once
+        assert_eq!(days[0].time, Some(TimeRange::parse("08:30 - 16:00").unwrap()));
+        assert_eq!(activities(&days[0]), ["SQL", "Joins", "Views"]);
+        assert_eq!(days[0].description, "SQL, Joins\nViews, SQL");
+        assert_eq!(days[0].learning_field.as_deref(), Some("LF05"));
+        assert_eq!(activities(&days[1]), ["Git"]);
+    }
+
+    #[test]
+    fn keeps_a_duplicate_day_once() {
+        let day = entry("2024-09-02", "08:30 - 12:00", None, &["SQL"]);
+
+        let days = merge_days(&[day.clone(), day], false);
+
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].description, "SQL");
+        assert_eq!(activities(&days[0]), ["SQL"]);
+    }
+
+    #[test]
+    fn groups_the_activities_by_learning_field() {
+        let entries = [
+            entry("2024-09-02", "08:30 - 10:00", Some("LF08"), &["Joins"]),
+            entry("2024-09-02", "10:00 - 11:00", None, &["Teamrunde"]),
+            entry("2024-09-02", "11:00 - 12:00", Some("LF05"), &["Klassen"]),
+            entry("2024-09-02", "13:00 - 16:00", Some("LF08"), &["Normalformen", "Joins"]),
+        ];
+
+        // In the order of the first session of every group
+        assert_eq!(grouped_activities(&entries).into_iter().collect::<Vec<_>>(), ["LF08 (Joins, Normalformen)", "Teamrunde", "LF05 (Klassen)"]);
+
+        // Mixed days have no learning field, a single session is grouped as well
+        let day = merge_day(&entries, true);
+        assert_eq!(day.learning_field, None);
+        assert_eq!(activities(&merge_day(&entries[..1], true)), ["LF08 (Joins)"]);
+        assert_eq!(activities(&merge_day(&entries[..1], false)), ["Joins"]);
+    }
+}
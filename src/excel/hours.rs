@@ -0,0 +1,135 @@
+use crate::common::tables::{Attendance, TimeRange};
+use crate::utils::config::{BreakRule, Rounding};
+use crate::CONFIG;
+
+// Computes the hours of a day from the union of the session time ranges and the attendance times,
+// a late arrival or an early leave shortens the day, the break rules are deducted before rounding (the gaps between
+// the sessions count towards the break)
+pub fn compute_hours(sessions: &[TimeRange], attendance: Option<&Attendance>) -> f64 {
+    let attended = attendance.and_then(|a| a.time);
+
+    let ranges: Vec<TimeRange> = match (sessions.is_empty(), attended) {
+        (false, Some(attended)) => sessions.iter().filter_map(|session| intersect(session, &attended)).collect(),
+        (false, None) => sessions.to_vec(),
+        (true, Some(attended)) => vec![attended],
+        (true, None) => return CONFIG.get_default_hours() as f64,
+    };
+
+    let merged = union(ranges);
+    let minutes: i64 = merged.iter().map(|range| (range.to - range.from).num_minutes()).sum();
+    let gaps: i64 = merged.windows(2).map(|pair| (pair[1].from - pair[0].to).num_minutes()).sum();
+    let minutes = minutes - break_minutes(minutes, gaps, CONFIG.get_break_rules());
+
+    round_minutes(minutes.max(0), CONFIG.get_rounding())
+}
+
+fn intersect(a: &TimeRange, b: &TimeRange) -> Option<TimeRange> {
+    let (from, to) = (a.from.max(b.from), a.to.min(b.to));
+    if from < to { Some(TimeRange { from, to }) } else { None }
+}
+
+// The ranges merged into disjoint ones in time order, overlapping sessions are only counted once
+fn union(mut ranges: Vec<TimeRange>) -> Vec<TimeRange> {
+    ranges.sort_by_key(|range| range.from);
+
+    let mut merged: Vec<TimeRange> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(open) if range.from <= open.to => open.to = open.to.max(range.to),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+// The break of the longest rule that applies to the worked minutes, less the minutes already taken between the sessions
+fn break_minutes(minutes: i64, gaps: i64, rules: &[BreakRule]) -> i64 {
+    rules.iter()
+        .filter(|rule| minutes > rule.after_minutes as i64)
+        .max_by_key(|rule| rule.after_minutes)
+        .map_or(0, |rule| (rule.minutes as i64 - gaps).max(0))
+}
+
+fn round_minutes(minutes: i64, rounding: Rounding) -> f64 {
+    let step = match rounding {
+        Rounding::Quarter => 15.0,
+        Rounding::Hour => 60.0,
+    };
+
+    (minutes as f64 / step).round() * step / 60.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use crate::common::tables::AttendanceStatus;
+    use crate::utils::testing::init;
+
+    fn range(raw: &str) -> TimeRange {
+        TimeRange::parse(raw).unwrap()
+    }
+
+    fn attended(raw: &str) -> Attendance {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 2).unwrap();
+        Attendance { date, time: Some(range(raw)), status: AttendanceStatus::Late, remarks: String::new(), duration: None }
+    }
+
+    fn rules() -> Vec<BreakRule> {
+        vec![BreakRule { after_minutes: 360, minutes: 30 }, BreakRule { after_minutes: 540, minutes: 45 }]
+    }
+
+    #[test]
+    fn merges_overlapping_sessions() {
+        let merged = union(vec![range("13:00 - 16:00"), range("08:00 - 12:00"), range("11:00 - 12:30")]);
+
+        assert_eq!(merged, vec![range("08:00 - 12:30"), range("13:00 - 16:00")]);
+    }
+
+    #[test]
+    fn applies_the_longest_break_rule() {
+        assert_eq!(break_minutes(360, 0, &rules()), 0);
+        assert_eq!(break_minutes(361, 0, &rules()), 30);
+        assert_eq!(break_minutes(600, 0, &rules()), 45);
+        assert_eq!(break_minutes(600, 0, &[]), 0);
+    }
+
+    #[test]
+    fn counts_the_gaps_between_sessions_as_break() {
+        assert_eq!(break_minutes(450, 60, &rules()), 0);
+        assert_eq!(break_minutes(450, 20, &rules()), 10);
+        assert_eq!(break_minutes(600, 30, &rules()), 15);
+    }
+
+    #[test]
+    fn rounds_to_quarters_or_hours() {
+        assert_eq!(round_minutes(442, Rounding::Quarter), 7.25);
+        assert_eq!(round_minutes(443, Rounding::Quarter), 7.5);
+        assert_eq!(round_minutes(450, Rounding::Hour), 8.0);
+        assert_eq!(round_minutes(449, Rounding::Hour), 7.0);
+    }
+
+    // The shipped configuration: quarter hours, 30 minutes after 6 hours and 45 after 9
+    #[test]
+    fn computes_the_hours_of_a_day() {
+        init();
+        let sessions = [range("08:00 - 12:00"), range("13:00 - 16:30")];
+
+        // The lunch hour between the sessions already is the break
+        assert_eq!(compute_hours(&sessions, None), 7.5);
+        assert_eq!(compute_hours(&[range("08:00 - 15:30")], None), 7.0);
+        assert_eq!(compute_hours(&[], None), 8.0);
+        assert_eq!(compute_hours(&[], Some(&attended("09:00 - 13:00"))), 4.0);
+    }
+
+    #[test]
+    fn shortens_the_day_by_a_late_arrival() {
+        init();
+        let sessions = [range("08:00 - 12:00"), range("13:00 - 16:30")];
+
+        assert_eq!(compute_hours(&sessions, Some(&attended("09:10 - 16:30"))), 6.25);
+        // Only the attended part of the sessions counts
+        assert_eq!(compute_hours(&sessions, Some(&attended("12:15 - 16:30"))), 3.5);
+    }
+}
@@ -1 +1,3 @@
+pub mod days;
+pub mod hours;
 pub mod process;
\ No newline at end of file
@@ -1,12 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use chrono::{Datelike, NaiveDate, NaiveTime};
+use std::path::Path;
+use chrono::{Datelike, NaiveDate, Weekday};
 use lazy_static::lazy_static;
+use linked_hash_set::LinkedHashSet;
 use log::{debug, info};
-use xlsxwriter::format::{FormatAlignment, FormatBorder, FormatVerticalAlignment};
+use xlsxwriter::format::{FormatAlignment, FormatBorder, FormatColor, FormatVerticalAlignment};
 use xlsxwriter::{Format, Workbook, Worksheet};
 use xlsxwriter::prelude::{GridLines, WorksheetCol, WorksheetRow};
-use crate::common::tables::{Attendance, Cell, ClassbookEntry, Course};
+use crate::common::parse::ENTRY_DATE_FORMAT;
+use crate::common::tables::{Attendance, Cell, ClassbookEntry, Course, DateRange};
+use crate::excel::days::{merge_days, sessions_by_date};
+use crate::excel::hours::compute_hours;
+use crate::overlay::holidays::apply_holidays;
+use crate::overlay::merge::{apply_overlay, load_overlay};
+use crate::overlay::training::{fill_training_period, training_period};
+use crate::utils::config::AbsencePolicy;
 use crate::CONFIG;
 
 const GRIDLINE_SETTINGS: GridLines = GridLines::HideAllGridLines;
@@ -20,6 +29,8 @@ const FORMAT_BORDER_NONE: FormatBorder = FormatBorder::None;
 const FORMAT_BORDER_MEDIUM: FormatBorder = FormatBorder::Medium;
 const FORMAT_BORDER_THIN: FormatBorder = FormatBorder::Thin;
 
+const HIGHLIGHT_COLOR: FormatColor = FormatColor::Custom(0xFFF2CC);
+
 const DAY: &str = "Tag";
 const OPERATIONAL_TASKS: &str = "Betriebliche Tätigkeiten, Unterweisungen, Berufsschulunterricht";
 const HOURS: &str = "Stunden";
@@ -41,7 +52,6 @@ const HOLIDAY: &'static str = "Feiertag";
 const FREE_UPPERCASE: &'static str = "FREI";
 const FREE_LOWERCASE: &'static str = "Frei";
 const NO_LESSON_FREE: &'static str = "Unterrichtsfrei";
-const HEALTH_REASON_ABSENCE: &str = "Keine Teilnahme am Unterricht aus gesundheitlichen Gründen";
 
 const MONDAY: &'static str = "Montag";
 const TUESDAY: &'static str = "Dienstag";
@@ -49,12 +59,6 @@ const WEDNESDAY: &'static str = "Mittwoch";
 const THURSDAY: &'static str = "Donnerstag";
 const FRIDAY: &'static str = "Freitag";
 
-const MON: &'static str = "Mon";
-const TUE: &'static str = "Tue";
-const WED: &'static str = "Wed";
-const THU: &'static str = "Thu";
-const FRI: &'static str = "Fri";
-
 const DAYS_AND_RANGES: &[(&str, (usize, usize, usize, usize))] = &[
     (MONDAY, (3, 0, 13, 0)),
     (TUESDAY, (14, 0, 24, 0)),
@@ -64,24 +68,24 @@ const DAYS_AND_RANGES: &[(&str, (usize, usize, usize, usize))] = &[
 ];
 
 lazy_static! {
-    static ref WEEKDAY_TO_RANGE: HashMap<String, (usize, usize, usize, usize)> = {
+    static ref WEEKDAY_TO_RANGE: HashMap<Weekday, (usize, usize, usize, usize)> = {
         let mut m = HashMap::new();
-        m.insert(MON.to_string(), (3, 1, 13, 8));
-        m.insert(TUE.to_string(), (14, 1, 24, 8));
-        m.insert(WED.to_string(), (25, 1, 35, 8));
-        m.insert(THU.to_string(), (36, 1, 46, 8));
-        m.insert(FRI.to_string(), (47, 1, 57, 8));
+        m.insert(Weekday::Mon, (3, 1, 13, 8));
+        m.insert(Weekday::Tue, (14, 1, 24, 8));
+        m.insert(Weekday::Wed, (25, 1, 35, 8));
+        m.insert(Weekday::Thu, (36, 1, 46, 8));
+        m.insert(Weekday::Fri, (47, 1, 57, 8));
 
         return m;
     };
 
-    static ref WEEKDAY_TO_ROW: HashMap<String, usize> = {
+    static ref WEEKDAY_TO_ROW: HashMap<Weekday, usize> = {
         let mut m = HashMap::new();
-        m.insert(MON.to_string(), 13);
-        m.insert(TUE.to_string(), 24);
-        m.insert(WED.to_string(), 35);
-        m.insert(THU.to_string(), 46);
-        m.insert(FRI.to_string(), 57);
+        m.insert(Weekday::Mon, 13);
+        m.insert(Weekday::Tue, 24);
+        m.insert(Weekday::Wed, 35);
+        m.insert(Weekday::Thu, 46);
+        m.insert(Weekday::Fri, 57);
 
         return m;
     };
@@ -91,8 +95,7 @@ lazy_static! {
         HOLIDAY,
         FREE_UPPERCASE,
         FREE_LOWERCASE,
-        NO_LESSON_FREE,
-        HEALTH_REASON_ABSENCE
+        NO_LESSON_FREE
     ];
 
     static ref ENTRIES: Vec<(usize, &'static str)> = vec![
@@ -103,93 +106,162 @@ lazy_static! {
     ];
 }
 
-// Check if the attendance is valid
-pub fn is_attendance_valid(attendance: &Attendance) -> bool {
-    let from_time = NaiveTime::parse_from_str(&attendance.from_time, "%H:%M")
-        .map_err(|e| { info!("Failed to parse from time: {} for date {}. Error: {}", &attendance.from_time, &attendance.date, e); e })
-        .ok();
-
-    let to_time = NaiveTime::parse_from_str(&attendance.to_time, "%H:%M")
-        .map_err(|e| { info!("Failed to parse to time: {} for date {}. Error: {}", &attendance.to_time, &attendance.date, e); e })
-        .ok();
+// Process a single course and write it to the workbook, only the weeks with a day in the range get a sheet
+pub async fn process_course(courses: &Vec<Course>, attendances: &Vec<Attendance>, path: &Path, range: DateRange) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let filename = path.to_str().ok_or("The report book path is not valid UTF-8")?;
+    let workbook = Workbook::new(filename)?;
 
-    let start_time = NaiveTime::from_hms_opt(0, 0, 0)
-        .ok_or("Invalid start time")
-        .unwrap();
-    let end_time = NaiveTime::from_hms_opt(23, 59, 59)
-        .ok_or("Invalid end time")
-        .unwrap();
+    // Flatten and collect all entries from all courses, merged with the overlay
+    let entries = report_entries(courses)?;
 
-    if let (Some(from), Some(to)) = (from_time, to_time) {
-        return from >= start_time && to <= end_time;
+    if entries.is_empty() {
+        return Ok(());
     }
 
-    return false;
-}
+    // One day block per date, the hours are computed from all sessions of the day
+    let sessions = sessions_by_date(&entries);
+    let mut all_entries = merge_days(&entries, CONFIG.is_group_by_learning_field());
 
-// Process a single course and write it to the workbook
-pub async fn process_course(courses: &Vec<Course>, attendances: &Vec<Attendance>) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let filename = "Reports.xlsx";
-    let workbook = Workbook::new(&filename)?;
+    // Days marked by their absence policy
+    let mut highlighted = HashSet::new();
 
-    // Initialize week number
-    let mut week_number = 0;
+    for entry in &mut all_entries {
+        let attendance = attendances.iter().find(|a| a.date == entry.date);
+
+        if let Some(attendance) = attendance {
+            if let Some(policy) = CONFIG.get_absence_policy(attendance.status) {
+                debug!("Absent: {} ({})", entry.date.format(ENTRY_DATE_FORMAT), attendance.status);
+                apply_absence_policy(entry, attendance, policy);
 
-    // Flatten and collect all entries from all courses
-    let mut all_entries: Vec<_> = courses.iter().flat_map(|course| course.classbook.entries.clone()).collect();
+                if policy.highlight {
+                    highlighted.insert(entry.date);
+                }
+            }
+        }
 
-    // Sort all entries by date and weekday
-    all_entries.sort_by(|a, b| {
-        let a_date = NaiveDate::parse_from_str(&a.date, "%d.%m.%y")
-            .unwrap_or_else(|_| panic!("Failed to parse date: {}", &a.date));
-        let b_date = NaiveDate::parse_from_str(&b.date, "%d.%m.%y")
-            .unwrap_or_else(|_| panic!("Failed to parse date: {}", &b.date));
+        // Hours set by the overlay, the holiday calendar or the absence policy are kept
+        if entry.hours.is_none() {
+            let is_zero_hour_day = entry.activities.iter().any(|activity| ZERO_HOUR_KEYWORDS.iter().any(|keyword| activity.contains(keyword)));
+            entry.hours = Some(if is_zero_hour_day { 0f64 } else { compute_hours(sessions.get(&entry.date).map_or(&[], |sessions| sessions.as_slice()), attendance) });
+        }
+    }
 
-        a_date.cmp(&b_date).then_with(|| a.weekday.cmp(&b.weekday))
-    });
+    // The weeks are numbered before the range is applied, so the "Nr." stays the same as in the full report book
+    for (week_number, week_entries) in group_by_week(&all_entries).iter().enumerate() {
+        if !week_entries.iter().any(|entry| range.contains(entry.date)) {
+            continue;
+        }
 
-    if all_entries.is_empty() {
-        return Ok(());
+        process_week(week_entries, week_number as u32, &workbook, &highlighted)?;
     }
 
-    let mut week_entries = vec![];
-    let first_date = NaiveDate::parse_from_str(&all_entries[0].date, "%d.%m.%y")?;
-    let mut last_week = first_date.iso_week().week();
+    workbook.close()?;
+    info!("Successfully wrote reports to {}", &filename);
 
-    for entry in &mut all_entries {
-        let entry_date = NaiveDate::parse_from_str(&entry.date, "%d.%m.%y")?;
+    Ok(())
+}
 
-        if let Some(attendance) = attendances.iter().find(|a| {
-            let attendance_date = NaiveDate::parse_from_str(&a.date, "%d.%m.%Y");
-            attendance_date == Ok(entry_date)
-        }) {
-            if !is_attendance_valid(attendance) {
-                debug!("Invalid attendance: {} {} {}", &entry_date.format("%d.%m.%y"), &attendance.from_time, &attendance.to_time);
+// Shows an absent day as configured: the policy text replaces the activities (or is put in front of them),
+// the hours of the policy are used unless the overlay or the holiday calendar already set them
+fn apply_absence_policy(entry: &mut ClassbookEntry, attendance: &Attendance, policy: &AbsencePolicy) {
+    // Without remarks only the placeholder and its own parentheses are dropped, other parentheses of the text are kept
+    let remarks = attendance.remarks.trim();
+    let text = if remarks.is_empty() {
+        policy.text.replace(" ({remarks})", "").replace("({remarks})", "").replace("{remarks}", "").trim().to_string()
+    } else {
+        policy.text.replace("{remarks}", remarks)
+    };
 
-                entry.activities.clear();
-                entry.activities.insert(HEALTH_REASON_ABSENCE.to_string());
-            }
-        }
+    let mut activities = LinkedHashSet::new();
+    if !text.is_empty() {
+        activities.insert(text);
+    }
+    if policy.keep_activities {
+        activities.extend(entry.activities.iter().cloned());
+    }
+
+    entry.activities = activities;
+    entry.hours = entry.hours.or(Some(policy.hours));
+}
 
-        if entry_date.iso_week().week() != last_week {
-            process_week(&mut week_entries, week_number, &workbook)?;
-            week_number += 1;
-            week_entries.clear();
+// The entries of the report book: the scraped entries with the public holidays, merged with the overlay file,
+// the remaining days of the training period are filled with the default text
+pub fn report_entries(courses: &[Course]) -> Result<Vec<ClassbookEntry>, Box<dyn Error + Send + Sync>> {
+    let mut entries = collect_entries(courses);
+    let training_period = training_period(CONFIG.get_training_start_date(), CONFIG.get_training_end_date())?;
+
+    if CONFIG.is_holidays_enabled() {
+        if let Some((first, last)) = report_span(&entries, training_period) {
+            apply_holidays(&mut entries, first, last, CONFIG.get_state());
         }
+    }
 
-        week_entries.push(entry);
-        last_week = entry_date.iso_week().week();
+    if let Some(overlay) = load_overlay(CONFIG.get_overlay_path())? {
+        apply_overlay(&mut entries, &overlay)?;
     }
 
-    // Don't forget to process the last week
-    if !week_entries.is_empty() {
-        process_week(&mut week_entries, week_number, &workbook)?;
+    if let Some((start, end)) = training_period {
+        fill_training_period(&mut entries, start, end, CONFIG.get_training_default_text());
     }
 
-    workbook.close()?;
-    info!("Successfully wrote reports to {}", &filename);
+    sort_entries(&mut entries);
+    Ok(entries)
+}
 
-    Ok(())
+// The first and the last date of the report book, including the training period
+fn report_span(entries: &[ClassbookEntry], training_period: Option<(NaiveDate, NaiveDate)>) -> Option<(NaiveDate, NaiveDate)> {
+    let mut dates: Vec<NaiveDate> = entries.iter().map(|entry| entry.date).collect();
+
+    if let Some((start, end)) = training_period {
+        dates.push(start);
+        dates.push(end);
+    }
+
+    dates.iter().min().copied().zip(dates.iter().max().copied())
+}
+
+// Flatten the entries of all courses with their Lernfeld and sort them by date and start time
+pub fn collect_entries(courses: &[Course]) -> Vec<ClassbookEntry> {
+    let mut entries: Vec<ClassbookEntry> = courses.iter()
+        .flat_map(|course| course.classbooks.iter().flat_map(|classbook| classbook.entries.iter()).cloned().map(move |mut entry| {
+            if !course.course.is_empty() {
+                entry.learning_field = Some(course.course.clone());
+            }
+            entry
+        }))
+        .collect();
+
+    sort_entries(&mut entries);
+    entries
+}
+
+// The courses and classbooks finish scraping in any order, the full sort key keeps the merged days deterministic
+// Entries without a time (e.g. appended by the overlay) come after the sessions of their day
+fn sort_entries(entries: &mut [ClassbookEntry]) {
+    entries.sort_by(|a, b| a.date.cmp(&b.date)
+        .then_with(|| a.time.is_none().cmp(&b.time.is_none()))
+        .then_with(|| a.time.map(|time| (time.from, time.to)).cmp(&b.time.map(|time| (time.from, time.to))))
+        .then_with(|| a.learning_field.cmp(&b.learning_field))
+        .then_with(|| a.description.cmp(&b.description)));
+}
+
+// Group sorted entries into the weeks of the report book, a new week starts whenever the calendar week changes
+pub fn group_by_week(entries: &[ClassbookEntry]) -> Vec<Vec<&ClassbookEntry>> {
+    let mut weeks: Vec<Vec<&ClassbookEntry>> = Vec::new();
+    let mut last_week = None;
+
+    for entry in entries {
+        let week = entry.date.iso_week();
+
+        match weeks.last_mut() {
+            Some(week_entries) if last_week == Some(week) => week_entries.push(entry),
+            _ => weeks.push(vec![entry]),
+        }
+
+        last_week = Some(week);
+    }
+
+    weeks
 }
 
 // Function to set worksheet gridlines, print scale, print area and fit to pages
@@ -388,13 +460,13 @@ fn write_day_header(worksheet: &mut Worksheet) -> Result<(), Box<dyn Error + Sen
 }
 
 // HashMap for faster weekday lookup
-fn entries_by_weekday<'a>(week_entries: &'a Vec<&'a ClassbookEntry>) -> HashMap<String, &'a ClassbookEntry> {
-    week_entries.iter().map(|&entry| (entry.weekday.clone(), entry)).collect()
+fn entries_by_weekday<'a>(week_entries: &'a Vec<&'a ClassbookEntry>) -> HashMap<Weekday, &'a ClassbookEntry> {
+    week_entries.iter().map(|&entry| (entry.weekday, entry)).collect()
 }
 
 // Function to write the activities to the worksheet
-fn write_activities(worksheet: &mut Worksheet, week_entries: &Vec<&ClassbookEntry>) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let format = create_format_from_props(FormatProps {
+fn write_activities(worksheet: &mut Worksheet, week_entries: &Vec<&ClassbookEntry>, highlighted: &HashSet<NaiveDate>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let format_props = FormatProps {
         font_size: Some(10.0),
         font_name: "Arial",
         alignment: FormatAlignment::Left,
@@ -406,7 +478,12 @@ fn write_activities(worksheet: &mut Worksheet, week_entries: &Vec<&ClassbookEntr
         text_wrap: true,
         bold: false,
         rotation: None,
-    });
+    };
+    let format = create_format_from_props(format_props.clone());
+
+    // Absent days can be marked with a background color
+    let mut highlight_format = create_format_from_props(format_props);
+    highlight_format.set_bg_color(HIGHLIGHT_COLOR);
 
     // Create a HashMap for faster lookup
     let entries_hashmap = entries_by_weekday(week_entries);
@@ -435,7 +512,7 @@ fn write_activities(worksheet: &mut Worksheet, week_entries: &Vec<&ClassbookEntr
             end_row as WorksheetRow,
             end_col as WorksheetCol,
             &activities_str,
-            Some(&format),
+            Some(if entry.is_some_and(|entry| highlighted.contains(&entry.date)) { &highlight_format } else { &format }),
         )?;
     }
 
@@ -463,13 +540,8 @@ fn write_hours(worksheet: &mut Worksheet, week_entries: &Vec<&ClassbookEntry>) -
         let value = week_entries
             .iter()
             .find(|e| e.weekday == *weekday)
-            .map_or(0f64, |entry| {
-                if entry.activities.iter().any(|activity| ZERO_HOUR_KEYWORDS.iter().any(|keyword| activity.contains(keyword))) {
-                    0f64
-                } else {
-                    8f64
-                }
-            });
+            .and_then(|entry| entry.hours)
+            .unwrap_or(0f64);
 
         worksheet.write_number(row as WorksheetRow, 9, value, Some(&format))?;
     }
@@ -492,7 +564,7 @@ fn create_and_merge(worksheet: &mut Worksheet, start_row: WorksheetRow, start_co
     Ok(())
 }
 
-fn write_summary(worksheet: &mut Worksheet) -> Result<(), Box<dyn Error + Send + Sync>> {
+fn write_summary(worksheet: &mut Worksheet, week_hours: f64) -> Result<(), Box<dyn Error + Send + Sync>> {
     let base_props = FormatProps {
         font_size: None,
         font_name: FONT_NAME,
@@ -525,7 +597,7 @@ fn write_summary(worksheet: &mut Worksheet) -> Result<(), Box<dyn Error + Send +
         bold: false,
         rotation: None,
     });
-    worksheet.write_formula_num(58, 9, "=SUM(J14:J58)", Some(&format), 30.)?;
+    worksheet.write_formula_num(58, 9, "=SUM(J14:J58)", Some(&format), week_hours)?;
 
     let border_medium_center_props = FormatProps { v_alignment: FormatVerticalAlignment::VerticalCenter, border_top: Some(FormatBorder::Medium), border_bottom: Some(FormatBorder::Medium), border_left: Some(FormatBorder::Medium), ..base_props.clone() };
     create_and_merge(worksheet, 59, 0, 62, 1, SIGNATURE, FormatProps { font_size: Some(FONT_SIZE_SMALL), ..border_medium_center_props.clone() })?;
@@ -544,10 +616,10 @@ fn write_summary(worksheet: &mut Worksheet) -> Result<(), Box<dyn Error + Send +
 }
 
 // Process a week of entries and write them to the workbook
-fn process_week(week_entries: &Vec<&ClassbookEntry>, week_number: u32, workbook: &Workbook) -> Result<(), Box<dyn Error + Send + Sync>> {
+fn process_week(week_entries: &Vec<&ClassbookEntry>, week_number: u32, workbook: &Workbook, highlighted: &HashSet<NaiveDate>) -> Result<(), Box<dyn Error + Send + Sync>> {
     let (start_date, end_date) = (
-        &week_entries.first().unwrap().date,
-        &week_entries.last().unwrap().date,
+        week_entries.first().unwrap().date.format(ENTRY_DATE_FORMAT),
+        week_entries.last().unwrap().date.format(ENTRY_DATE_FORMAT),
     );
     let date_range = format!("{} - {}", start_date, end_date);
     let mut worksheet = workbook.add_worksheet(Some(&format!("{}", &date_range)))?;
@@ -676,9 +748,71 @@ fn process_week(week_entries: &Vec<&ClassbookEntry>, week_number: u32, workbook:
     worksheet.merge_range(1, 0, 1, 3, TRAINING_LOCATION, Some(&format))?;
 
     write_day_header(&mut worksheet)?;
-    write_activities(&mut worksheet, &week_entries)?;
+    write_activities(&mut worksheet, &week_entries, highlighted)?;
     write_hours(&mut worksheet, &week_entries)?;
-    write_summary(&mut worksheet)?;
+    write_summary(&mut worksheet, week_entries.iter().filter_map(|entry| entry.hours).sum())?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tables::{AttendanceStatus, OverlayFile, TimeRange};
+
+    fn absent_day(remarks: &str) -> (ClassbookEntry, Attendance) {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 2).unwrap();
+        let entry = ClassbookEntry {
+            weekday: date.weekday(),
+            date,
+            time: None,
+            description: String::new(),
+            activities: LinkedHashSet::new(),
+            hours: None,
+            learning_field: None,
+        };
+        let attendance = Attendance { date, time: None, status: AttendanceStatus::Excused, remarks: remarks.to_string(), duration: None };
+
+        (entry, attendance)
+    }
+
+    fn session(from: &str, to: &str, learning_field: &str, activity: &str) -> ClassbookEntry {
+        let (mut entry, _) = absent_day("");
+        entry.time = Some(TimeRange::parse(&format!("{} - {}", from, to)).unwrap());
+        entry.learning_field = Some(learning_field.to_string());
+        entry.activities.insert(activity.to_string());
+        entry
+    }
+
+    #[test]
+    fn appends_the_overlay_to_the_whole_day() {
+        let overlay: OverlayFile = toml::from_str("[[entry]]\ndate = 2024-09-02\npolicy = \"append\"\nactivities = [\"Projektarbeit\"]\nhours = 6.0\n").unwrap();
+        let mut entries = vec![session("13:00", "16:00", "LF09", "Netzwerke"), session("08:00", "12:00", "LF08", "SQL")];
+
+        apply_overlay(&mut entries, &overlay).unwrap();
+        sort_entries(&mut entries);
+
+        // The activities follow those of every session, also when they are grouped by learning field
+        let day = &merge_days(&entries, false)[0];
+        assert_eq!(day.activities.iter().collect::<Vec<_>>(), ["SQL", "Netzwerke", "Projektarbeit"]);
+        assert_eq!(day.hours, Some(6.0));
+
+        let day = &merge_days(&entries, true)[0];
+        assert_eq!(day.activities.iter().collect::<Vec<_>>(), ["LF08 (SQL)", "LF09 (Netzwerke)", "Projektarbeit"]);
+        assert_eq!(day.hours, Some(6.0));
+    }
+
+    #[test]
+    fn drops_only_the_placeholder_without_remarks() {
+        let policy = AbsencePolicy { text: "Abwesend (Schule) ({remarks})".to_string(), hours: 7.5, keep_activities: false, highlight: false };
+
+        let (mut entry, attendance) = absent_day("");
+        apply_absence_policy(&mut entry, &attendance, &policy);
+        assert_eq!(entry.activities.iter().collect::<Vec<_>>(), ["Abwesend (Schule)"]);
+        assert_eq!(entry.hours, Some(7.5));
+
+        let (mut entry, attendance) = absent_day(" Arzttermin ");
+        apply_absence_policy(&mut entry, &attendance, &policy);
+        assert_eq!(entry.activities.iter().collect::<Vec<_>>(), ["Abwesend (Schule) (Arzttermin)"]);
+    }
+}
@@ -0,0 +1,266 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use chrono::Local;
+use clap::ValueEnum;
+use log::info;
+use serde_derive::Serialize;
+use crate::common::parse::TIME_FORMAT;
+use crate::common::tables::{Attendance, ClassbookEntry, Course, Dataset};
+
+// Version of the exported schema, increased on every incompatible change
+pub const SCHEMA_VERSION: u32 = 1;
+
+const ACTIVITY_SEPARATOR: &str = " | ";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// Supported export formats
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl ExportFormat {
+    // Default file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+// One line of the NDJSON export
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Record<'a> {
+    Dataset { schema_version: u32, generated_at: &'a str },
+    Course { id: &'a str, name: &'a str, link: &'a str, course: &'a str, duration: &'a str },
+    Classbook { course_id: &'a str, id: &'a str, link: &'a str, direct_link: &'a Option<String> },
+    Entry { course_id: &'a str, classbook_id: &'a str, #[serde(flatten)] entry: &'a ClassbookEntry },
+    Attendance(&'a Attendance),
+}
+
+// One row of the classbook CSV export
+#[derive(Serialize)]
+struct EntryRow<'a> {
+    schema_version: u32,
+    course_id: &'a str,
+    course: &'a str,
+    course_name: &'a str,
+    course_duration: &'a str,
+    classbook_id: &'a str,
+    weekday: String,
+    date: String,
+    time: String,
+    description: &'a str,
+    activities: String,
+}
+
+// One row of the attendance CSV export
+#[derive(Serialize)]
+struct AttendanceRow<'a> {
+    schema_version: u32,
+    date: String,
+    from_time: String,
+    to_time: String,
+    status: String,
+    remarks: &'a str,
+    duration: Option<u32>,
+}
+
+// Writes the scraped model in the given format
+pub fn export_dataset(courses: &[Course], attendances: &[Attendance], format: ExportFormat, path: &Path) -> Result<(), Box<dyn Error>> {
+    let dataset = Dataset {
+        schema_version: SCHEMA_VERSION,
+        generated_at: Local::now().to_rfc3339(),
+        courses: courses.to_vec(),
+        attendances: attendances.to_vec(),
+    };
+
+    match format {
+        ExportFormat::Json => write_json(&dataset, path)?,
+        ExportFormat::Ndjson => write_ndjson(&dataset, path)?,
+        ExportFormat::Csv => write_csv(&dataset, path)?,
+    }
+
+    Ok(())
+}
+
+fn write_json(dataset: &Dataset, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, dataset)?;
+    writer.flush()?;
+
+    info!("Exported {} courses to {}", dataset.courses.len(), path.display());
+    Ok(())
+}
+
+fn write_ndjson(dataset: &Dataset, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut write_record = |record: Record| -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    };
+
+    write_record(Record::Dataset { schema_version: dataset.schema_version, generated_at: &dataset.generated_at })?;
+
+    for course in &dataset.courses {
+        write_record(Record::Course {
+            id: &course.id,
+            name: &course.name,
+            link: &course.link,
+            course: &course.course,
+            duration: &course.duration,
+        })?;
+
+        for classbook in &course.classbooks {
+            write_record(Record::Classbook {
+                course_id: &course.id,
+                id: &classbook.id,
+                link: &classbook.link,
+                direct_link: &classbook.direct_link,
+            })?;
+
+            for entry in &classbook.entries {
+                write_record(Record::Entry { course_id: &course.id, classbook_id: &classbook.id, entry })?;
+            }
+        }
+    }
+
+    for attendance in &dataset.attendances {
+        write_record(Record::Attendance(attendance))?;
+    }
+
+    writer.flush()?;
+
+    info!("Exported {} courses to {}", dataset.courses.len(), path.display());
+    Ok(())
+}
+
+// The classbook entries go to the given file, the attendance to a sibling "<name>.attendance.csv"
+fn write_csv(dataset: &Dataset, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+
+    for course in &dataset.courses {
+        for (classbook, entry) in course.classbooks.iter().flat_map(|classbook| classbook.entries.iter().map(move |entry| (classbook, entry))) {
+            writer.serialize(EntryRow {
+                schema_version: dataset.schema_version,
+                course_id: &course.id,
+                course: &course.course,
+                course_name: &course.name,
+                course_duration: &course.duration,
+                classbook_id: &classbook.id,
+                weekday: entry.weekday.to_string(),
+                date: entry.date.format(DATE_FORMAT).to_string(),
+                time: entry.time.map(|time| time.to_string()).unwrap_or_default(),
+                description: &entry.description,
+                activities: entry.activities.iter().cloned().collect::<Vec<_>>().join(ACTIVITY_SEPARATOR),
+            })?;
+        }
+    }
+    writer.flush()?;
+
+    let attendance_path = attendance_csv_path(path);
+    let mut writer = csv::Writer::from_path(&attendance_path)?;
+
+    for attendance in &dataset.attendances {
+        writer.serialize(AttendanceRow {
+            schema_version: dataset.schema_version,
+            date: attendance.date.format(DATE_FORMAT).to_string(),
+            from_time: attendance.time.map(|time| time.from.format(TIME_FORMAT).to_string()).unwrap_or_default(),
+            to_time: attendance.time.map(|time| time.to.format(TIME_FORMAT).to_string()).unwrap_or_default(),
+            status: attendance.status.to_string(),
+            remarks: &attendance.remarks,
+            duration: attendance.duration,
+        })?;
+    }
+    writer.flush()?;
+
+    info!("Exported {} courses to {} and {}", dataset.courses.len(), path.display(), attendance_path.display());
+    Ok(())
+}
+
+fn attendance_csv_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!("{}.attendance.csv", stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, NaiveDate};
+    use linked_hash_set::LinkedHashSet;
+    use crate::common::tables::{AttendanceStatus, Classbook, TimeRange};
+    use crate::export::import::import_dataset;
+
+    fn sample() -> (Vec<Course>, Vec<Attendance>) {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 2).unwrap();
+        let entry = ClassbookEntry {
+            weekday: date.weekday(),
+            date,
+            time: Some(TimeRange::parse("08:30 - 12:00").unwrap()),
+            description: "SQL, Joins".to_string(),
+            activities: ["SQL", "Joins"].into_iter().map(String::from).collect::<LinkedHashSet<_>>(),
+            hours: None,
+            learning_field: None,
+        };
+        let course = Course {
+            id: "42".to_string(),
+            name: "Fachinformatiker".to_string(),
+            link: "https://lernplattform.gfn.de/course/view.php?id=42".to_string(),
+            course: "FIAE".to_string(),
+            duration: "2024 - 2026".to_string(),
+            classbooks: vec![Classbook { id: "7".to_string(), link: String::new(), direct_link: None, entries: vec![entry] }],
+            failed_classbooks: Vec::new(),
+        };
+        let attendance = Attendance { date, time: TimeRange::parse("08:30 - 12:00").ok(), status: AttendanceStatus::Late, remarks: "Bahn".to_string(), duration: Some(200) };
+
+        (vec![course], vec![attendance])
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("moodle-report-portfolio-export-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn imports_the_json_export() {
+        let (courses, attendances) = sample();
+        let path = temp_path("dataset.json");
+
+        export_dataset(&courses, &attendances, ExportFormat::Json, &path).unwrap();
+        let (imported_courses, imported_attendances) = import_dataset(&path).unwrap();
+
+        // The model has no PartialEq, the serialized forms are compared
+        assert_eq!(serde_json::to_value(&imported_courses).unwrap(), serde_json::to_value(&courses).unwrap());
+        assert_eq!(serde_json::to_value(&imported_attendances).unwrap(), serde_json::to_value(&attendances).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writes_the_csv_headers() {
+        let (courses, attendances) = sample();
+        let path = temp_path("dataset.csv");
+
+        export_dataset(&courses, &attendances, ExportFormat::Csv, &path).unwrap();
+        let entries = std::fs::read_to_string(&path).unwrap();
+        let attendance = std::fs::read_to_string(attendance_csv_path(&path)).unwrap();
+
+        let mut lines = entries.lines();
+        assert_eq!(lines.next(), Some("schema_version,course_id,course,course_name,course_duration,classbook_id,weekday,date,time,description,activities"));
+        assert_eq!(lines.next(), Some("1,42,FIAE,Fachinformatiker,2024 - 2026,7,Mon,2024-09-02,08:30 - 12:00,\"SQL, Joins\",SQL | Joins"));
+
+        let mut lines = attendance.lines();
+        assert_eq!(lines.next(), Some("schema_version,date,from_time,to_time,status,remarks,duration"));
+        assert!(lines.next().unwrap().starts_with("1,2024-09-02,08:30,12:00,"));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(attendance_csv_path(&path)).unwrap();
+    }
+}
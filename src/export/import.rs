@@ -0,0 +1,144 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use chrono::Datelike;
+use log::info;
+use crate::common::tables::{Attendance, Course, Dataset};
+use crate::export::dataset::SCHEMA_VERSION;
+
+// Reads a dataset written by the JSON export (or edited by hand) and validates it
+pub fn import_dataset(path: &Path) -> Result<(Vec<Course>, Vec<Attendance>), Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let deserializer = &mut serde_json::Deserializer::from_reader(reader);
+
+    // Report the path of the offending field, e.g. "courses[2].classbooks[0].entries[5].date"
+    let dataset: Dataset = serde_path_to_error::deserialize(deserializer)
+        .map_err(|e| format!("{}: {}: {}", path.display(), e.path(), e.inner()))?;
+
+    if dataset.schema_version != SCHEMA_VERSION {
+        return Err(format!("{}: schema_version: unsupported version {}, expected {}", path.display(), dataset.schema_version, SCHEMA_VERSION).into());
+    }
+
+    let errors = validate_dataset(&dataset);
+    if !errors.is_empty() {
+        return Err(format!("{} contains {} invalid fields:\n  {}", path.display(), errors.len(), errors.join("\n  ")).into());
+    }
+
+    info!("Imported {} courses and {} attendance records from {}", dataset.courses.len(), dataset.attendances.len(), path.display());
+    Ok((dataset.courses, dataset.attendances))
+}
+
+// Checks the required fields, the weekdays and the attendance times, every problem is reported with its field path
+fn validate_dataset(dataset: &Dataset) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    for (i, course) in dataset.courses.iter().enumerate() {
+        let course_path = format!("courses[{}]", i);
+        require(&mut errors, &format!("{}.id", course_path), &course.id);
+        require(&mut errors, &format!("{}.name", course_path), &course.name);
+
+        // Dates and times are already checked while deserializing, the weekday has to match the date
+        for (k, classbook) in course.classbooks.iter().enumerate() {
+            require(&mut errors, &format!("{}.classbooks[{}].id", course_path, k), &classbook.id);
+
+            for (j, entry) in classbook.entries.iter().enumerate() {
+                if entry.weekday != entry.date.weekday() {
+                    errors.push(format!("{}.classbooks[{}].entries[{}].weekday: \"{}\" does not match the date {} (expected \"{}\")",
+                        course_path, k, j, entry.weekday, entry.date, entry.date.weekday()));
+                }
+            }
+        }
+    }
+
+    // An inverted range already fails to deserialize, an empty one would count as an attended day without minutes
+    for (i, attendance) in dataset.attendances.iter().enumerate() {
+        if let Some(time) = attendance.time.filter(|time| time.to <= time.from) {
+            errors.push(format!("attendances[{}].time: \"{}\" is an empty or inverted time range", i, time));
+        }
+    }
+
+    errors
+}
+
+fn require(errors: &mut Vec<String>, path: &str, value: &str) {
+    if value.trim().is_empty() {
+        errors.push(format!("{}: must not be empty", path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn dataset() -> Value {
+        json!({
+            "schema_version": SCHEMA_VERSION,
+            "generated_at": "2024-09-03T08:00:00+02:00",
+            "courses": [{
+                "id": "42",
+                "name": "Fachinformatiker",
+                "link": "",
+                "course": "FIAE",
+                "duration": "",
+                "classbooks": [{
+                    "id": "7",
+                    "link": "",
+                    "direct_link": null,
+                    "entries": [{ "weekday": "Mon", "date": "2024-09-02", "time": "08:30 - 12:00", "description": "SQL", "activities": ["SQL"] }],
+                }],
+            }],
+            "attendances": [{ "date": "2024-09-02", "time": "08:30 - 12:00", "status": "present" }],
+        })
+    }
+
+    // Imports the dataset after the edit and returns the error
+    fn import_error(name: &str, edit: impl FnOnce(&mut Value)) -> String {
+        let mut dataset = dataset();
+        edit(&mut dataset);
+
+        let path = std::env::temp_dir().join(format!("moodle-report-portfolio-import-{}-{}.json", std::process::id(), name));
+        std::fs::write(&path, serde_json::to_vec(&dataset).unwrap()).unwrap();
+        let result = import_dataset(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        result.err().expect("the dataset should not import").to_string()
+    }
+
+    #[test]
+    fn imports_a_valid_dataset() {
+        let path = std::env::temp_dir().join(format!("moodle-report-portfolio-import-{}-valid.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_vec(&dataset()).unwrap()).unwrap();
+
+        let (courses, attendances) = import_dataset(&path).unwrap();
+        assert_eq!(courses[0].classbooks[0].entries.len(), 1);
+        assert_eq!(attendances.len(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reports_the_path_of_an_invalid_field() {
+        let error = import_error("weekday", |dataset| dataset["courses"][0]["classbooks"][0]["entries"][0]["weekday"] = json!("Tue"));
+        assert!(error.contains("courses[0].classbooks[0].entries[0].weekday: \"Tue\" does not match the date 2024-09-02 (expected \"Mon\")"), "{}", error);
+
+        let error = import_error("id", |dataset| dataset["courses"][0]["id"] = json!(" "));
+        assert!(error.contains("courses[0].id: must not be empty"), "{}", error);
+
+        let error = import_error("date", |dataset| dataset["courses"][0]["classbooks"][0]["entries"][0]["date"] = json!("02.09.2024"));
+        assert!(error.contains("courses[0].classbooks[0].entries[0].date: "), "{}", error);
+
+        let error = import_error("version", |dataset| dataset["schema_version"] = json!(SCHEMA_VERSION + 1));
+        assert!(error.contains("schema_version: unsupported version"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_an_empty_or_inverted_attendance() {
+        let error = import_error("empty", |dataset| dataset["attendances"][0]["time"] = json!("08:30 - 08:30"));
+        assert!(error.contains("attendances[0].time: \"08:30 - 08:30\" is an empty or inverted time range"), "{}", error);
+
+        let error = import_error("inverted", |dataset| dataset["attendances"][0]["time"] = json!("12:00 - 08:30"));
+        assert!(error.contains("attendances[0].time: "), "{}", error);
+    }
+}
@@ -1,28 +1,72 @@
 mod auth;
 mod class;
+mod commands;
 mod common;
 mod excel;
+mod export;
+mod overlay;
+mod store;
 mod utils;
+mod webservice;
 
-use futures::future::try_join_all;
-use crate::utils::config::Config;
-use lazy_static::lazy_static;
+use clap::Parser;
+use crate::utils::config::{default_config_path, load_config, set_config_path, SharedConfig};
 use log::{error, info};
-use std::sync::Arc;
-use crate::auth::client::create_client;
-use crate::auth::cookies::save_cookies;
-use crate::auth::login::login;
-use crate::class::attendance::scrape_attendance;
-use crate::class::course::scrape_courses;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use crate::commands::data::{check_failures, fetch_data, load_from_store, open_store, set_network_mode, sync_store};
+use crate::commands::doctor::run_doctor;
+use crate::commands::export::run_export;
+use crate::commands::login::run_login;
+use crate::commands::render::run_render;
+use crate::commands::scrape::run_scrape;
+use crate::commands::vault::run_vault;
+use crate::common::tables::DateRange;
 use crate::excel::process::process_course;
+use crate::export::dataset::export_dataset;
+use crate::export::import::import_dataset;
+use crate::utils::args::{Args, Command, NetworkArgs};
+use crate::utils::exit::{Failure, FailureClass, OrFail};
 use crate::utils::logger::setup_logger;
+use crate::utils::wizard::run_init;
 
-lazy_static! {
-    static ref CONFIG: Config = Config::new().unwrap();
-}
+// The configuration, loaded once at the start of main
+static CONFIG: SharedConfig = SharedConfig;
 
 #[tokio::main]
-async fn main() -> Result<(), String> {
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("Error: {}", failure);
+            failure.class.exit_code()
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), Failure> {
+    // The init wizard writes the configuration, so it runs before anything is loaded
+    if !matches!(args.command, Some(Command::Init { .. })) {
+        start(args.config.clone())?;
+    }
+
+    match args.command {
+        Some(Command::Init { force }) => init_config(args.config, force),
+        Some(Command::Login { network }) => run_login(&network).await,
+        Some(Command::Scrape { network, filter, output, full }) => run_scrape(&network, &filter, output, full).await,
+        Some(Command::Render { source, filter, output }) => run_render(&source, &filter, output).await,
+        Some(Command::Export { format, source, filter, output }) => run_export(format, &source, &filter, output),
+        Some(Command::Doctor) => run_doctor().await,
+        Some(Command::Vault { action }) => run_vault(&action),
+        None => run_all(args).await,
+    }
+}
+
+// Sets up the logger and loads the configuration, the file given on the command line replaces the lookup chain
+fn start(config: Option<PathBuf>) -> Result<(), Failure> {
     // Configure the logger
     match setup_logger() {
         Ok(()) => info!("Logger set up successfully"),
@@ -36,38 +80,58 @@ async fn main() -> Result<(), String> {
     // GitHub
     info!("GitHub: https://github.com/ccmvn/moodle-report-portfolio");
 
-    // Create the client and cookie store
-    let (client, cookie_store) = create_client().await.map_err(|e| format!("Failed to create the client: {}", e))?;
+    // Load the configuration
+    if let Some(path) = config {
+        set_config_path(path);
+    }
+    if let Err(e) = load_config() {
+        error!("{}", e);
+        return Err(Failure::new(FailureClass::Config, "Failed to load the configuration"));
+    }
+
+    Ok(())
+}
+
+// Writes a starter configuration with the answers of the wizard
+fn init_config(config: Option<PathBuf>, force: bool) -> Result<(), Failure> {
+    let path = config.unwrap_or_else(default_config_path);
+    let path = run_init(&path, force).or_fail(FailureClass::Config, "Failed to write the configuration")?;
+    println!("Wrote {}, choose a password source in the [account] section or enter it at the login", path.display());
 
-    // Login to the platform
-    if let Ok(_) = login(&client).await {
-        info!("Logged in successfully");
-        info!("Read courses and create XLSX file...");
+    Ok(())
+}
 
-        // Wrap the Client in an Arc
-        let client = Arc::new(client);
+// The run without a subcommand: fetches (or imports) the data and creates the report book or an export in one go
+async fn run_all(args: Args) -> Result<(), Failure> {
+    set_network_mode(&NetworkArgs { record: args.record, replay: args.replay })?;
 
-        // Scrape the global attendance information
-        let attendances = scrape_attendance(Arc::clone(&client)).await.map_err(|e| format!("Failed to scrape attendance: {}", e))?;
+    // Open the local store, an imported dataset is rendered as is
+    let mut store = if args.import.is_none() { open_store(args.offline)? } else { None };
 
-        // Scrape the courses
-        let courses = scrape_courses(Arc::clone(&client)).await;
-        // Process the courses
-        let course_futures = courses.iter().map(|course| { process_course(course, &attendances) });
+    let ((courses, attendances), failures) = match (&args.import, store.as_mut()) {
+        (Some(path), _) => {
+            info!("Rendering from the imported dataset {}", path.display());
 
-        // Wait for all courses to be processed
-        if let Err(e) = try_join_all(course_futures).await {
-            return Err(format!("Failed to process courses: {}", e));
+            (import_dataset(path).or_fail(FailureClass::Data, "Failed to import the dataset")?, Vec::new())
         }
+        (None, Some(store)) if args.offline => {
+            let last_sync = store.last_sync().or_fail(FailureClass::Data, "Failed to read the local store")?;
+            info!("Offline mode, rendering from the local store (last sync: {})", last_sync.unwrap_or_else(|| "never".to_string()));
 
-        // Save the cookies
-        if let Err(e) = save_cookies(cookie_store) {
-            return Err(format!("Failed to save the cookies: {}", e));
+            (load_from_store(store)?, Vec::new())
         }
+        (None, Some(store)) => sync_store(store, args.full).await?,
+        (None, None) => fetch_data(&HashSet::new()).await?,
+    };
 
+    // Export the data or process the courses
+    if let Some(format) = args.export {
+        let path = args.output.unwrap_or_else(|| PathBuf::from(format!("Reports.{}", format.extension())));
+        export_dataset(&courses, &attendances, format, &path).or_fail(FailureClass::Output, "Failed to export the data")?;
     } else {
-        return Err("Failed to login: Invalid credentials".to_string());
+        process_course(&courses, &attendances, &PathBuf::from("Reports.xlsx"), DateRange::default()).await
+            .or_fail(FailureClass::Output, "Failed to process courses")?;
     }
 
-    Ok(())
+    check_failures(&failures)
 }
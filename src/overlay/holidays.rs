@@ -0,0 +1,188 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use linked_hash_set::LinkedHashSet;
+use log::debug;
+use crate::common::parse::ENTRY_DATE_FORMAT;
+use crate::common::tables::ClassbookEntry;
+use crate::utils::config::FederalState;
+use crate::utils::config::FederalState::*;
+
+const HOLIDAY: &str = "Feiertag";
+
+// Public holidays of a year, the nationwide ones and those of the given federal state
+fn holidays(year: i32, state: Option<FederalState>) -> Vec<(NaiveDate, &'static str)> {
+    let easter = easter_sunday(year);
+    let date = |month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    let is_in = |states: &[FederalState]| state.is_some_and(|state| states.contains(&state));
+
+    let mut holidays = vec![
+        (date(1, 1), "Neujahr"),
+        (easter - Duration::days(2), "Karfreitag"),
+        (easter + Duration::days(1), "Ostermontag"),
+        (date(5, 1), "Tag der Arbeit"),
+        (easter + Duration::days(39), "Christi Himmelfahrt"),
+        (easter + Duration::days(50), "Pfingstmontag"),
+        (date(10, 3), "Tag der Deutschen Einheit"),
+        (date(12, 25), "1. Weihnachtstag"),
+        (date(12, 26), "2. Weihnachtstag"),
+    ];
+
+    if is_in(&[BW, BY, ST]) {
+        holidays.push((date(1, 6), "Heilige Drei Könige"));
+    }
+    if (is_in(&[BE]) && year >= 2019) || (is_in(&[MV]) && year >= 2023) {
+        holidays.push((date(3, 8), "Internationaler Frauentag"));
+    }
+    if is_in(&[BB]) {
+        holidays.push((easter, "Ostersonntag"));
+        holidays.push((easter + Duration::days(49), "Pfingstsonntag"));
+    }
+    if is_in(&[BW, BY, HE, NW, RP, SL]) {
+        holidays.push((easter + Duration::days(60), "Fronleichnam"));
+    }
+    if is_in(&[SL]) {
+        holidays.push((date(8, 15), "Mariä Himmelfahrt"));
+    }
+    if is_in(&[TH]) && year >= 2019 {
+        holidays.push((date(9, 20), "Weltkindertag"));
+    }
+    // The 500th anniversary of the Reformation was a nationwide holiday
+    if year == 2017 || is_in(&[BB, MV, SN, ST, TH]) || (is_in(&[HB, HH, NI, SH]) && year >= 2018) {
+        holidays.push((date(10, 31), "Reformationstag"));
+    }
+    if is_in(&[BW, BY, NW, RP, SL]) {
+        holidays.push((date(11, 1), "Allerheiligen"));
+    }
+    if is_in(&[SN]) {
+        holidays.push((repentance_day(year), "Buß- und Bettag"));
+    }
+
+    holidays.sort();
+    holidays
+}
+
+// Marks the public holidays between the first and the last date of the report book as "Feiertag" with 0 hours,
+// whatever the classbook says, and adds the holidays that have no entry at all
+pub fn apply_holidays(entries: &mut Vec<ClassbookEntry>, first: NaiveDate, last: NaiveDate, state: Option<FederalState>) {
+    for year in first.year()..=last.year() {
+        for (date, name) in holidays(year, state) {
+            if date < first || date > last || matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+                continue;
+            }
+
+            debug!("Holiday -> Date: {}, Name: {}", date.format(ENTRY_DATE_FORMAT), name);
+
+            let mut activities = LinkedHashSet::new();
+            activities.insert(format!("{} ({})", HOLIDAY, name));
+
+            entries.retain(|entry| entry.date != date);
+            entries.push(ClassbookEntry {
+                weekday: date.weekday(),
+                date,
+                time: None,
+                description: HOLIDAY.to_string(),
+                activities,
+                hours: Some(0.0),
+                learning_field: None,
+            });
+        }
+    }
+}
+
+// Easter Sunday (anonymous Gregorian algorithm)
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+// Buß- und Bettag: the last Wednesday before the 23rd of November
+fn repentance_day(year: i32) -> NaiveDate {
+    let mut date = NaiveDate::from_ymd_opt(year, 11, 22).unwrap();
+    while date.weekday() != Weekday::Wed {
+        date = date.pred_opt().unwrap();
+    }
+    date
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn names(year: i32, state: Option<FederalState>) -> Vec<&'static str> {
+        holidays(year, state).into_iter().map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn computes_easter_sunday() {
+        assert_eq!(easter_sunday(2024), date(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), date(2025, 4, 20));
+        assert_eq!(easter_sunday(2019), date(2019, 4, 21));
+        assert_eq!(easter_sunday(2038), date(2038, 4, 25));
+    }
+
+    #[test]
+    fn derives_the_movable_holidays_from_easter() {
+        let holidays = holidays(2024, Some(NW));
+
+        assert!(holidays.contains(&(date(2024, 3, 29), "Karfreitag")));
+        assert!(holidays.contains(&(date(2024, 4, 1), "Ostermontag")));
+        assert!(holidays.contains(&(date(2024, 5, 9), "Christi Himmelfahrt")));
+        assert!(holidays.contains(&(date(2024, 5, 20), "Pfingstmontag")));
+        assert!(holidays.contains(&(date(2024, 5, 30), "Fronleichnam")));
+    }
+
+    #[test]
+    fn keeps_the_regional_holidays_to_their_states() {
+        assert!(holidays(2024, Some(SN)).contains(&(date(2024, 11, 20), "Buß- und Bettag")));
+        assert!(!names(2024, Some(BY)).contains(&"Buß- und Bettag"));
+        assert!(!names(2024, None).contains(&"Buß- und Bettag"));
+
+        assert!(names(2024, Some(BY)).contains(&"Heilige Drei Könige"));
+        assert!(!names(2024, Some(NW)).contains(&"Heilige Drei Könige"));
+
+        // Without a state only the nationwide holidays count, the Reformation day was one in 2017
+        assert_eq!(names(2024, None).len(), 9);
+        assert!(names(2017, None).contains(&"Reformationstag"));
+        assert!(!names(2018, Some(NW)).contains(&"Reformationstag"));
+        assert!(names(2018, Some(NI)).contains(&"Reformationstag"));
+    }
+
+    #[test]
+    fn replaces_the_entries_of_weekday_holidays() {
+        let mut entries = vec![ClassbookEntry {
+            weekday: Weekday::Thu,
+            date: date(2024, 10, 3),
+            time: None,
+            description: "Unterricht".to_string(),
+            activities: LinkedHashSet::new(),
+            hours: Some(8.0),
+            learning_field: None,
+        }];
+
+        // The 1st of January 2022 was a Saturday
+        apply_holidays(&mut entries, date(2021, 12, 31), date(2024, 10, 31), None);
+
+        let holiday = entries.iter().find(|entry| entry.date == date(2024, 10, 3)).unwrap();
+        assert_eq!(holiday.description, HOLIDAY);
+        assert_eq!(holiday.hours, Some(0.0));
+        assert_eq!(entries.iter().filter(|entry| entry.date == date(2024, 10, 3)).count(), 1);
+        assert!(entries.iter().all(|entry| entry.date != date(2022, 1, 1)));
+    }
+}
@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use chrono::{Datelike, NaiveDate, Weekday};
+use linked_hash_set::LinkedHashSet;
+use log::{debug, info};
+use toml::value::Datetime;
+use crate::common::parse::ENTRY_DATE_FORMAT;
+use crate::common::tables::{ClassbookEntry, MergePolicy, OverlayEntry, OverlayFile, OverlayKind};
+
+const SCHOOL: &str = "Berufsschule";
+const COMPANY: &str = "Betriebliche Tätigkeit";
+const VACATION: &str = "Urlaub";
+
+// Reads the overlay file, a missing file simply means there is nothing to merge
+pub fn load_overlay(path: &str) -> Result<Option<OverlayFile>, Box<dyn Error + Send + Sync>> {
+    if path.is_empty() || !Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(path)?;
+    let overlay: OverlayFile = toml::from_str(&contents).map_err(|e| format!("Failed to parse the overlay file {}: {}", path, e))?;
+
+    info!("Loaded {} overlay entries from {}", overlay.entries.len(), path);
+    Ok(Some(overlay))
+}
+
+// Merges the overlay entries (in file order) into the classbook entries, each one according to its merge policy
+// An appended entry applies to the whole day: its activities follow those of all sessions and its hours replace the ones of the day
+pub fn apply_overlay(entries: &mut Vec<ClassbookEntry>, overlay: &OverlayFile) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for (i, overlay_entry) in overlay.entries.iter().enumerate() {
+        let dates = overlay_dates(overlay_entry).map_err(|e| format!("Invalid overlay entry {}: {}", i + 1, e))?;
+
+        for date in dates {
+            let entry = to_classbook_entry(overlay_entry, date);
+            let existing = entries.iter().any(|e| e.date == date);
+
+            match (overlay_entry.policy, existing) {
+                (MergePolicy::Replace, _) => {
+                    entries.retain(|e| e.date != date);
+                    entries.push(entry);
+                }
+                (MergePolicy::Append, true) => {
+                    if overlay_entry.hours.is_some() {
+                        entries.iter_mut().filter(|e| e.date == date).for_each(|e| e.hours = overlay_entry.hours);
+                    }
+
+                    // A separate entry without a time or a learning field, merged into the day after its sessions
+                    entries.push(ClassbookEntry {
+                        time: None,
+                        description: String::new(),
+                        activities: overlay_entry.activities.iter().cloned().collect(),
+                        hours: overlay_entry.hours,
+                        ..entry
+                    });
+                }
+                (MergePolicy::Add, true) => continue,
+                (MergePolicy::Append, false) | (MergePolicy::Add, false) => entries.push(entry),
+            }
+
+            debug!("Overlay -> Date: {}, Policy: {:?}", date.format(ENTRY_DATE_FORMAT), overlay_entry.policy);
+        }
+    }
+
+    Ok(())
+}
+
+// The weekdays covered by an overlay entry, either a single date or a from/to range
+fn overlay_dates(entry: &OverlayEntry) -> Result<Vec<NaiveDate>, String> {
+    let (from, to) = match (&entry.date, &entry.from, &entry.to) {
+        (Some(date), None, None) => (to_naive_date(date)?, to_naive_date(date)?),
+        (None, Some(from), Some(to)) => (to_naive_date(from)?, to_naive_date(to)?),
+        _ => return Err("either date or from and to must be set".to_string()),
+    };
+
+    if from > to {
+        return Err(format!("from ({}) is after to ({})", from, to));
+    }
+
+    Ok(from.iter_days()
+        .take_while(|date| *date <= to)
+        .filter(|date| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun))
+        .collect())
+}
+
+fn to_naive_date(datetime: &Datetime) -> Result<NaiveDate, String> {
+    let date = datetime.date.ok_or_else(|| format!("{} is not a date", datetime))?;
+    NaiveDate::from_ymd_opt(date.year as i32, date.month as u32, date.day as u32).ok_or_else(|| format!("{} is not a valid date", datetime))
+}
+
+fn to_classbook_entry(overlay_entry: &OverlayEntry, date: NaiveDate) -> ClassbookEntry {
+    let label = kind_label(overlay_entry.kind);
+
+    let mut activities: LinkedHashSet<String> = overlay_entry.activities.iter().cloned().collect();
+    if activities.is_empty() && !label.is_empty() {
+        activities.insert(label.to_string());
+    }
+
+    // Vacation days don't count as working hours unless stated otherwise
+    let hours = match overlay_entry.kind {
+        OverlayKind::Vacation => overlay_entry.hours.or(Some(0.0)),
+        _ => overlay_entry.hours,
+    };
+
+    ClassbookEntry {
+        weekday: date.weekday(),
+        date,
+        time: overlay_entry.time,
+        description: label.to_string(),
+        activities,
+        hours,
+        learning_field: None,
+    }
+}
+
+fn kind_label(kind: OverlayKind) -> &'static str {
+    match kind {
+        OverlayKind::School => SCHOOL,
+        OverlayKind::Company => COMPANY,
+        OverlayKind::Vacation => VACATION,
+        OverlayKind::Other => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tables::TimeRange;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn session(day: &str, activity: &str) -> ClassbookEntry {
+        ClassbookEntry {
+            weekday: date(day).weekday(),
+            date: date(day),
+            time: Some(TimeRange::parse("08:30 - 16:00").unwrap()),
+            description: activity.to_string(),
+            activities: [activity.to_string()].into_iter().collect(),
+            hours: None,
+            learning_field: Some("LF05".to_string()),
+        }
+    }
+
+    fn merged(entries: &mut Vec<ClassbookEntry>, overlay: &str) -> Result<(), String> {
+        let overlay: OverlayFile = toml::from_str(overlay).unwrap();
+        apply_overlay(entries, &overlay).map_err(|e| e.to_string())
+    }
+
+    fn activities_of(entries: &[ClassbookEntry], day: &str) -> Vec<String> {
+        entries.iter().filter(|entry| entry.date == date(day)).flat_map(|entry| entry.activities.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn replaces_the_scraped_day() {
+        let mut entries = vec![session("2024-09-02", "SQL"), session("2024-09-02", "Joins"), session("2024-09-03", "Git")];
+
+        merged(&mut entries, "[[entry]]\ndate = 2024-09-02\nkind = \"company\"\n").unwrap();
+
+        assert_eq!(activities_of(&entries, "2024-09-02"), [COMPANY]);
+        let replaced = entries.iter().find(|entry| entry.date == date("2024-09-02")).unwrap();
+        assert_eq!((replaced.time, replaced.learning_field.as_deref()), (None, None));
+        assert_eq!(activities_of(&entries, "2024-09-03"), ["Git"]);
+    }
+
+    #[test]
+    fn adds_only_missing_days() {
+        let mut entries = vec![session("2024-09-02", "SQL")];
+
+        merged(&mut entries, "[[entry]]\nfrom = 2024-09-02\nto = 2024-09-03\npolicy = \"add\"\nkind = \"school\"\nactivities = [\"Wirtschaftslehre\"]\n").unwrap();
+
+        assert_eq!(activities_of(&entries, "2024-09-02"), ["SQL"]);
+        assert_eq!(activities_of(&entries, "2024-09-03"), ["Wirtschaftslehre"]);
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn covers_the_weekdays_of_a_range() {
+        // Friday to Tuesday, the weekend is skipped; the days are not in the scraped data
+        let mut entries = Vec::new();
+
+        merged(&mut entries, "[[entry]]\nfrom = 2024-09-06\nto = 2024-09-10\nkind = \"vacation\"\n").unwrap();
+
+        let dates: Vec<NaiveDate> = entries.iter().map(|entry| entry.date).collect();
+        assert_eq!(dates, ["2024-09-06", "2024-09-09", "2024-09-10"].map(date));
+        assert!(entries.iter().all(|entry| entry.hours == Some(0.0) && entry.description == VACATION));
+    }
+
+    #[test]
+    fn rejects_an_invalid_range() {
+        let mut entries = Vec::new();
+
+        let error = merged(&mut entries, "[[entry]]\nfrom = 2024-09-10\nto = 2024-09-06\n").unwrap_err();
+        assert!(error.contains("Invalid overlay entry 1: from (2024-09-10) is after to (2024-09-06)"), "{}", error);
+
+        let error = merged(&mut entries, "[[entry]]\ndate = 2024-09-02\n\n[[entry]]\nfrom = 2024-09-02\n").unwrap_err();
+        assert!(error.contains("Invalid overlay entry 2: either date or from and to must be set"), "{}", error);
+    }
+}
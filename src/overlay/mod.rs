@@ -0,0 +1,3 @@
+pub mod holidays;
+pub mod merge;
+pub mod training;
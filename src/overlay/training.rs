@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::error::Error;
+use chrono::{Datelike, NaiveDate, Weekday};
+use linked_hash_set::LinkedHashSet;
+use log::debug;
+use crate::common::tables::ClassbookEntry;
+use crate::utils::config::CONFIG_DATE_FORMAT;
+
+// Parses the configured training period, it is disabled unless both dates are set
+pub fn training_period(start_date: &str, end_date: &str) -> Result<Option<(NaiveDate, NaiveDate)>, Box<dyn Error + Send + Sync>> {
+    if start_date.is_empty() || end_date.is_empty() {
+        return Ok(None);
+    }
+
+    let start = NaiveDate::parse_from_str(start_date, CONFIG_DATE_FORMAT)
+        .map_err(|e| format!("Invalid training start_date {}, expected YYYY-MM-DD: {}", start_date, e))?;
+    let end = NaiveDate::parse_from_str(end_date, CONFIG_DATE_FORMAT)
+        .map_err(|e| format!("Invalid training end_date {}, expected YYYY-MM-DD: {}", end_date, e))?;
+
+    if start > end {
+        return Err(format!("The training start_date {} is after the end_date {}", start_date, end_date).into());
+    }
+
+    Ok(Some((start, end)))
+}
+
+// Adds an entry with the default text for every weekday of the training period without an entry,
+// so every week of the period gets a sheet and the numbering has no holes
+pub fn fill_training_period(entries: &mut Vec<ClassbookEntry>, start: NaiveDate, end: NaiveDate, default_text: &str) {
+    let covered: HashSet<NaiveDate> = entries.iter().map(|entry| entry.date).collect();
+
+    let missing: Vec<NaiveDate> = start.iter_days()
+        .take_while(|date| *date <= end)
+        .filter(|date| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun))
+        .filter(|date| !covered.contains(date))
+        .collect();
+
+    debug!("Filling {} days of the training period with \"{}\"", missing.len(), default_text);
+
+    for date in missing {
+        let mut activities = LinkedHashSet::new();
+        if !default_text.is_empty() {
+            activities.insert(default_text.to_string());
+        }
+
+        entries.push(ClassbookEntry {
+            weekday: date.weekday(),
+            date,
+            time: None,
+            description: default_text.to_string(),
+            activities,
+            hours: None,
+            learning_field: None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, CONFIG_DATE_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn parses_the_training_period() {
+        assert_eq!(training_period("", "").unwrap(), None);
+        assert_eq!(training_period("2024-09-01", "").unwrap(), None);
+        assert_eq!(training_period("2024-09-02", "2024-09-02").unwrap(), Some((date("2024-09-02"), date("2024-09-02"))));
+        assert_eq!(training_period("2024-09-01", "2026-08-31").unwrap(), Some((date("2024-09-01"), date("2026-08-31"))));
+
+        assert!(training_period("2024-09-31", "2026-08-31").unwrap_err().to_string().contains("start_date"));
+        assert!(training_period("2024-09-01", "31.08.2026").unwrap_err().to_string().contains("end_date"));
+        assert!(training_period("2026-08-31", "2024-09-01").is_err());
+    }
+
+    #[test]
+    fn fills_the_weekdays_without_an_entry() {
+        let mut entries = Vec::new();
+        fill_training_period(&mut entries, date("2024-09-05"), date("2024-09-10"), "Selbststudium");
+        // Thursday to the next Tuesday without the weekend
+        let dates: Vec<NaiveDate> = entries.iter().map(|entry| entry.date).collect();
+        assert_eq!(dates, ["2024-09-05", "2024-09-06", "2024-09-09", "2024-09-10"].map(date));
+        assert!(entries.iter().all(|entry| entry.activities.contains("Selbststudium")));
+
+        // The days with an entry are kept as they are
+        let kept = entries.remove(1);
+        fill_training_period(&mut entries, date("2024-09-05"), date("2024-09-10"), "");
+        assert_eq!(entries.len(), 4);
+        let added = entries.iter().find(|entry| entry.date == kept.date).unwrap();
+        assert!(added.activities.is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use chrono::{Duration, Local, NaiveDate, Weekday};
+use linked_hash_set::LinkedHashSet;
+use log::{debug, info};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use crate::common::parse::{parse_time, TIME_FORMAT};
+use crate::common::tables::{Attendance, AttendanceStatus, Classbook, ClassbookEntry, Course, TimeRange};
+
+// Increased on every incompatible change of the tables, a store of a newer version is not opened
+pub const SCHEMA_VERSION: i32 = 1;
+
+const STORE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+const CREATE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS courses (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        link TEXT NOT NULL,
+        course TEXT NOT NULL,
+        duration TEXT NOT NULL,
+        synced_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS classbooks (
+        course_id TEXT NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+        id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        link TEXT NOT NULL,
+        direct_link TEXT,
+        PRIMARY KEY (course_id, id)
+    );
+    CREATE TABLE IF NOT EXISTS classbook_entries (
+        course_id TEXT NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+        classbook_id TEXT NOT NULL,
+        date TEXT NOT NULL,
+        time TEXT NOT NULL,
+        weekday TEXT NOT NULL,
+        description TEXT NOT NULL,
+        activities TEXT NOT NULL,
+        PRIMARY KEY (course_id, classbook_id, date, time)
+    );
+    CREATE TABLE IF NOT EXISTS attendances (
+        date TEXT PRIMARY KEY,
+        from_time TEXT NOT NULL,
+        to_time TEXT NOT NULL,
+        status TEXT NOT NULL,
+        remarks TEXT NOT NULL DEFAULT '',
+        duration INTEGER
+    );
+";
+
+// Local SQLite store of the scraped data
+pub struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    // Opens (or creates) the store, a store written by a newer version is refused instead of being overwritten
+    pub fn open(path: &str) -> Result<Store, Box<dyn Error>> {
+        let connection = Connection::open(Path::new(path))?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        let version: i32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(format!("{} has schema version {}, this version only reads up to {}", path, version, SCHEMA_VERSION).into());
+        }
+        if version == 0 {
+            connection.execute_batch(CREATE_TABLES)?;
+            connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+            info!("Created the local store in {}", path);
+        }
+
+        Ok(Store { connection })
+    }
+
+    // Opens an existing store without creating it, nothing can be written through it
+    pub fn open_read_only(path: &str) -> Result<Store, Box<dyn Error>> {
+        let connection = Connection::open_with_flags(Path::new(path), OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        Ok(Store { connection })
+    }
+
+    // The schema version of the store, 0 if it has no tables yet
+    pub fn schema_version(&self) -> Result<i32, Box<dyn Error>> {
+        Ok(self.connection.pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    // Returns the IDs of the courses that don't need to be fetched again
+    // A course is settled once it was synced more than `refresh_days` after its last session, settled courses are
+    // still fetched again once their last sync is `recheck_days` old, so late edits of the trainer are detected (0 never rechecks)
+    pub fn settled_courses(&self, refresh_days: i64, recheck_days: i64) -> Result<HashSet<String>, Box<dyn Error>> {
+        let today = Local::now().date_naive();
+        let mut statement = self.connection.prepare("
+            SELECT c.id, c.synced_at, MAX(e.date)
+            FROM courses c JOIN classbook_entries e ON e.course_id = c.id
+            GROUP BY c.id
+        ")?;
+
+        let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?;
+
+        let mut settled = HashSet::new();
+        for row in rows {
+            let (id, synced_at, last_date) = row?;
+            let synced_at = NaiveDate::parse_from_str(&synced_at, STORE_DATE_FORMAT)?;
+            let last_date = NaiveDate::parse_from_str(&last_date, STORE_DATE_FORMAT)?;
+
+            let recheck_due = recheck_days > 0 && synced_at + Duration::days(recheck_days) <= today;
+            if last_date + Duration::days(refresh_days) < synced_at && !recheck_due {
+                settled.insert(id);
+            }
+        }
+
+        debug!("Store -> {} settled courses", settled.len());
+        Ok(settled)
+    }
+
+    // Replaces the stored courses with their classbooks and classbook entries, except for the classbooks that failed in this run
+    pub fn save_courses(&mut self, courses: &[Course]) -> Result<(), Box<dyn Error>> {
+        let synced_at = Local::now().date_naive().format(STORE_DATE_FORMAT).to_string();
+        let transaction = self.connection.transaction()?;
+
+        for course in courses {
+            transaction.execute("
+                INSERT INTO courses (id, name, link, course, duration, synced_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name, link = excluded.link, course = excluded.course, duration = excluded.duration,
+                    synced_at = excluded.synced_at
+            ", params![course.id, course.name, course.link, course.course, course.duration, synced_at])?;
+
+            // A classbook that failed in this run keeps its stored entries until it can be fetched again
+            let stored_classbooks: Vec<String> = transaction.prepare("SELECT id FROM classbooks WHERE course_id = ?1")?
+                .query_map(params![course.id], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+
+            for classbook_id in stored_classbooks.iter().filter(|id| !course.failed_classbooks.contains(id)) {
+                transaction.execute("DELETE FROM classbook_entries WHERE course_id = ?1 AND classbook_id = ?2", params![course.id, classbook_id])?;
+                transaction.execute("DELETE FROM classbooks WHERE course_id = ?1 AND id = ?2", params![course.id, classbook_id])?;
+            }
+
+            for (position, classbook) in course.classbooks.iter().enumerate() {
+                transaction.execute("
+                    INSERT OR REPLACE INTO classbooks (course_id, id, position, link, direct_link) VALUES (?1, ?2, ?3, ?4, ?5)
+                ", params![course.id, classbook.id, position as i64, classbook.link, classbook.direct_link])?;
+
+                for entry in &classbook.entries {
+                    let date = entry.date.format(STORE_DATE_FORMAT).to_string();
+                    let time = entry.time.map(|time| time.to_string()).unwrap_or_default();
+                    let activities = serde_json::to_string(&entry.activities.iter().collect::<Vec<_>>())?;
+
+                    transaction.execute("
+                        INSERT OR REPLACE INTO classbook_entries (course_id, classbook_id, date, time, weekday, description, activities)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                    ", params![course.id, classbook.id, date, time, entry.weekday.to_string(), entry.description, activities])?;
+                }
+            }
+        }
+
+        transaction.commit()?;
+        debug!("Store -> Saved {} courses", courses.len());
+
+        Ok(())
+    }
+
+    // Adds new and updates known attendance records, older records are kept
+    pub fn save_attendances(&mut self, attendances: &[Attendance]) -> Result<(), Box<dyn Error>> {
+        let transaction = self.connection.transaction()?;
+
+        for attendance in attendances {
+            let date = attendance.date.format(STORE_DATE_FORMAT).to_string();
+            let from_time = attendance.time.map(|time| time.from.format(TIME_FORMAT).to_string()).unwrap_or_default();
+            let to_time = attendance.time.map(|time| time.to.format(TIME_FORMAT).to_string()).unwrap_or_default();
+
+            transaction.execute("
+                INSERT OR REPLACE INTO attendances (date, from_time, to_time, status, remarks, duration) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ", params![date, from_time, to_time, attendance.status.to_string(), attendance.remarks, attendance.duration])?;
+        }
+
+        transaction.commit()?;
+        debug!("Store -> Saved {} attendance records", attendances.len());
+
+        Ok(())
+    }
+
+    // Loads all stored courses with their classbooks and classbook entries
+    pub fn load_courses(&self) -> Result<Vec<Course>, Box<dyn Error>> {
+        let mut classbooks = self.load_classbooks()?;
+
+        let mut statement = self.connection.prepare("SELECT id, name, link, course, duration FROM courses ORDER BY id")?;
+
+        let rows = statement.query_map([], |row| {
+            Ok(Course {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                link: row.get(2)?,
+                course: row.get(3)?,
+                duration: row.get(4)?,
+                classbooks: Vec::new(),
+                failed_classbooks: Vec::new(),
+            })
+        })?;
+
+        let mut courses = Vec::new();
+        for row in rows {
+            let mut course = row?;
+            course.classbooks = classbooks.remove(&course.id).unwrap_or_default();
+            courses.push(course);
+        }
+
+        Ok(courses)
+    }
+
+    // Loads all stored attendance records
+    pub fn load_attendances(&self) -> Result<Vec<Attendance>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare("SELECT date, from_time, to_time, status, remarks, duration FROM attendances ORDER BY date")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?, row.get::<_, String>(4)?, row.get::<_, Option<u32>>(5)?))
+        })?;
+
+        let mut attendances = Vec::new();
+        for row in rows {
+            let (date, from_time, to_time, status, remarks, duration) = row?;
+            let date = NaiveDate::parse_from_str(&date, STORE_DATE_FORMAT)?;
+            let status = AttendanceStatus::from_str(&status).map_err(|e| format!("{} in the local store", e))?;
+
+            // Absences are stored without times
+            let time = match (parse_time(&from_time), parse_time(&to_time)) {
+                (Some(from), Some(to)) => Some(TimeRange { from, to }),
+                _ => None,
+            };
+
+            attendances.push(Attendance { date, time, status, remarks, duration });
+        }
+
+        Ok(attendances)
+    }
+
+    // Returns the date of the last sync, if any course was synced yet
+    pub fn last_sync(&self) -> Result<Option<String>, Box<dyn Error>> {
+        let last_sync = self.connection.query_row("SELECT MAX(synced_at) FROM courses", [], |row| row.get(0)).optional()?;
+        Ok(last_sync.flatten())
+    }
+
+    // Loads the classbooks with their entries grouped by course ID, in the scraped order
+    fn load_classbooks(&self) -> Result<HashMap<String, Vec<Classbook>>, Box<dyn Error>> {
+        let mut entries = self.load_entries()?;
+
+        let mut statement = self.connection.prepare("SELECT course_id, id, link, direct_link FROM classbooks ORDER BY course_id, position")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, Classbook { id: row.get(1)?, link: row.get(2)?, direct_link: row.get(3)?, entries: Vec::new() }))
+        })?;
+
+        let mut classbooks: HashMap<String, Vec<Classbook>> = HashMap::new();
+        for row in rows {
+            let (course_id, mut classbook) = row?;
+            classbook.entries = entries.remove(&(course_id.clone(), classbook.id.clone())).unwrap_or_default();
+            classbooks.entry(course_id).or_default().push(classbook);
+        }
+
+        Ok(classbooks)
+    }
+
+    // Loads the classbook entries grouped by course and classbook ID
+    fn load_entries(&self) -> Result<HashMap<(String, String), Vec<ClassbookEntry>>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare("
+            SELECT course_id, classbook_id, date, time, weekday, description, activities FROM classbook_entries
+            ORDER BY course_id, classbook_id, date, time
+        ")?;
+
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?, row.get::<_, String>(5)?, row.get::<_, String>(6)?))
+        })?;
+
+        let mut entries: HashMap<(String, String), Vec<ClassbookEntry>> = HashMap::new();
+        for row in rows {
+            let (course_id, classbook_id, date, time, weekday, description, activities) = row?;
+            let date = NaiveDate::parse_from_str(&date, STORE_DATE_FORMAT)?;
+            let weekday = Weekday::from_str(&weekday).map_err(|_| format!("Invalid weekday {} in the local store", weekday))?;
+            let time = if time.is_empty() { None } else { Some(TimeRange::parse(&time)?) };
+            let activities: LinkedHashSet<String> = serde_json::from_str::<Vec<String>>(&activities)?.into_iter().collect();
+
+            entries.entry((course_id, classbook_id)).or_default().push(ClassbookEntry { weekday, date, time, description, activities, hours: None, learning_field: None });
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_a_store_of_a_newer_version() {
+        let path = std::env::temp_dir().join(format!("moodle-report-portfolio-store-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let store = Store::open(path).unwrap();
+        assert_eq!(store.schema_version().unwrap(), SCHEMA_VERSION);
+        store.connection.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+        drop(store);
+
+        let error = Store::open(path).err().expect("a newer store should not open").to_string();
+        assert!(error.contains("only reads up to"), "{}", error);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
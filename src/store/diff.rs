@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use chrono::{Datelike, IsoWeek, NaiveDate};
+use crate::common::parse::{ATTENDANCE_DATE_FORMAT, ENTRY_DATE_FORMAT};
+use crate::common::tables::{Attendance, Course};
+use crate::excel::process::{group_by_week, report_entries};
+
+// Changes between the previous and the current snapshot
+pub struct ChangeReport {
+    pub days: Vec<DayChange>,
+    pub attendances: Vec<AttendanceChange>,
+    pub weeks: Vec<String>,
+}
+
+#[derive(PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+// A classbook day of a course that was added, removed or modified
+pub struct DayChange {
+    pub kind: ChangeKind,
+    pub course: String,
+    pub date: NaiveDate,
+    pub added_activities: Vec<String>,
+    pub removed_activities: Vec<String>,
+    pub old_description: Option<String>,
+    pub new_description: Option<String>,
+}
+
+// An attendance record that was added, removed or modified
+pub struct AttendanceChange {
+    pub date: NaiveDate,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+// All sessions of a course on one day
+struct Day {
+    course: String,
+    description: String,
+    activities: Vec<String>,
+}
+
+// Compares the previous snapshot with the current one
+pub fn detect_changes(previous_courses: &[Course], previous_attendances: &[Attendance], courses: &[Course], attendances: &[Attendance]) -> Result<ChangeReport, Box<dyn Error>> {
+    let old_days = days_by_course_and_date(previous_courses);
+    let new_days = days_by_course_and_date(courses);
+
+    let mut days = Vec::new();
+    for key in old_days.keys().chain(new_days.keys()).collect::<BTreeSet<_>>() {
+        let (_, date) = key;
+
+        match (old_days.get(key), new_days.get(key)) {
+            (None, Some(new)) => days.push(DayChange {
+                kind: ChangeKind::Added,
+                course: new.course.clone(),
+                date: *date,
+                added_activities: new.activities.clone(),
+                removed_activities: Vec::new(),
+                old_description: None,
+                new_description: Some(new.description.clone()),
+            }),
+            (Some(old), None) => days.push(DayChange {
+                kind: ChangeKind::Removed,
+                course: old.course.clone(),
+                date: *date,
+                added_activities: Vec::new(),
+                removed_activities: old.activities.clone(),
+                old_description: Some(old.description.clone()),
+                new_description: None,
+            }),
+            (Some(old), Some(new)) if old.activities != new.activities || old.description != new.description => days.push(DayChange {
+                kind: ChangeKind::Modified,
+                course: new.course.clone(),
+                date: *date,
+                added_activities: new.activities.iter().filter(|activity| !old.activities.contains(activity)).cloned().collect(),
+                removed_activities: old.activities.iter().filter(|activity| !new.activities.contains(activity)).cloned().collect(),
+                old_description: Some(old.description.clone()).filter(|_| old.description != new.description),
+                new_description: Some(new.description.clone()).filter(|_| old.description != new.description),
+            }),
+            _ => {}
+        }
+    }
+
+    let old_attendances = attendances_by_date(previous_attendances);
+    let new_attendances = attendances_by_date(attendances);
+
+    let mut attendance_changes = Vec::new();
+    for date in old_attendances.keys().chain(new_attendances.keys()).collect::<BTreeSet<_>>() {
+        let (old, new) = (old_attendances.get(date), new_attendances.get(date));
+
+        if old != new {
+            attendance_changes.push(AttendanceChange { date: *date, old: old.cloned(), new: new.cloned() });
+        }
+    }
+
+    // A changed attendance record changes the hours and the absences of its week as well
+    let dates = days.iter().map(|day| day.date).chain(attendance_changes.iter().map(|change| change.date));
+    let weeks = affected_weeks(previous_courses, dates)?;
+
+    Ok(ChangeReport { days, attendances: attendance_changes, weeks })
+}
+
+impl ChangeReport {
+    // Check if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.days.is_empty() && self.attendances.is_empty()
+    }
+
+    // Renders the report as Markdown
+    pub fn to_markdown(&self, generated_at: &str) -> String {
+        let mut report = format!("# Changes since the last run ({})\n", generated_at);
+
+        if !self.days.is_empty() {
+            report.push_str("\n## Classbook\n");
+
+            for day in &self.days {
+                let kind = match day.kind {
+                    ChangeKind::Added => "added",
+                    ChangeKind::Removed => "removed",
+                    ChangeKind::Modified => "modified",
+                };
+                report.push_str(&format!("\n### {} – {} ({})\n\n", day.course, day.date.format(ENTRY_DATE_FORMAT), kind));
+
+                for activity in &day.added_activities {
+                    report.push_str(&format!("- Added: {}\n", activity));
+                }
+                for activity in &day.removed_activities {
+                    report.push_str(&format!("- Removed: {}\n", activity));
+                }
+                if day.kind == ChangeKind::Modified {
+                    if let (Some(old), Some(new)) = (&day.old_description, &day.new_description) {
+                        report.push_str(&format!("- Old text: {}\n- New text: {}\n", old, new));
+                    }
+                }
+            }
+        }
+
+        if !self.attendances.is_empty() {
+            report.push_str("\n## Attendance\n\n");
+
+            for change in &self.attendances {
+                report.push_str(&format!("- {}: {} → {}\n",
+                    change.date.format(ATTENDANCE_DATE_FORMAT),
+                    change.old.as_deref().unwrap_or("none"),
+                    change.new.as_deref().unwrap_or("none")));
+            }
+        }
+
+        if !self.weeks.is_empty() {
+            report.push_str("\n## Affected report weeks\n\n");
+
+            for week in &self.weeks {
+                report.push_str(&format!("- {}\n", week));
+            }
+        }
+
+        report
+    }
+}
+
+// Groups the classbook entries by course and date, several sessions on one day are combined
+fn days_by_course_and_date(courses: &[Course]) -> BTreeMap<(String, NaiveDate), Day> {
+    let mut days: BTreeMap<(String, NaiveDate), Day> = BTreeMap::new();
+
+    for course in courses {
+        for entry in course.classbooks.iter().flat_map(|classbook| &classbook.entries) {
+            let day = days.entry((course.id.clone(), entry.date)).or_insert_with(|| Day {
+                course: format!("{} {}", course.course, course.name),
+                description: String::new(),
+                activities: Vec::new(),
+            });
+
+            if !day.description.is_empty() {
+                day.description.push('\n');
+            }
+            day.description.push_str(&entry.description);
+
+            for activity in &entry.activities {
+                if !day.activities.contains(activity) {
+                    day.activities.push(activity.clone());
+                }
+            }
+        }
+    }
+
+    days
+}
+
+fn attendances_by_date(attendances: &[Attendance]) -> BTreeMap<NaiveDate, String> {
+    attendances.iter()
+        .map(|attendance| {
+            let mut record = match attendance.time {
+                Some(time) => format!("{}, {}", time, attendance.status),
+                None => format!("no times ({})", attendance.status),
+            };
+            if !attendance.remarks.is_empty() {
+                record.push_str(&format!(", \"{}\"", attendance.remarks));
+            }
+
+            (attendance.date, record)
+        })
+        .collect()
+}
+
+// Lists the already generated weeks (numbered like the previous report book) that contain a changed day or attendance record
+fn affected_weeks(previous_courses: &[Course], dates: impl Iterator<Item = NaiveDate>) -> Result<Vec<String>, Box<dyn Error>> {
+    let entries = report_entries(previous_courses).map_err(|e| e as Box<dyn Error>)?;
+    let report_weeks: Vec<(IsoWeek, String)> = group_by_week(&entries)
+        .iter()
+        .map(|week_entries| {
+            let first = week_entries.first().unwrap();
+            let last = week_entries.last().unwrap();
+
+            (first.date.iso_week(), format!("{} - {}", first.date.format(ENTRY_DATE_FORMAT), last.date.format(ENTRY_DATE_FORMAT)))
+        })
+        .collect();
+
+    let mut weeks = Vec::new();
+    for iso_week in dates.map(|date| date.iso_week()).collect::<BTreeSet<_>>() {
+        let week = match report_weeks.iter().position(|(week, _)| *week == iso_week) {
+            Some(index) => format!("Nr. {} ({})", index + 1, report_weeks[index].1),
+            None => format!("New week {} of {}, the following weeks would be renumbered", iso_week.week(), iso_week.year()),
+        };
+        weeks.push(week);
+    }
+
+    Ok(weeks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linked_hash_set::LinkedHashSet;
+    use crate::common::tables::{AttendanceStatus, Classbook, ClassbookEntry};
+    use crate::utils::testing::init;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn course(days: &[(&str, &[&str])]) -> Vec<Course> {
+        let entries = days.iter()
+            .map(|(day, activities)| ClassbookEntry {
+                weekday: date(day).weekday(),
+                date: date(day),
+                time: None,
+                description: activities.join(", "),
+                activities: activities.iter().map(|activity| activity.to_string()).collect::<LinkedHashSet<_>>(),
+                hours: None,
+                learning_field: None,
+            })
+            .collect();
+
+        vec![Course {
+            id: "42".to_string(),
+            name: "Fachinformatiker".to_string(),
+            link: String::new(),
+            course: "FIAE".to_string(),
+            duration: String::new(),
+            classbooks: vec![Classbook { id: "1".to_string(), link: String::new(), direct_link: None, entries }],
+            failed_classbooks: Vec::new(),
+        }]
+    }
+
+    fn attendance(day: &str, status: AttendanceStatus) -> Attendance {
+        Attendance { date: date(day), time: None, status, remarks: String::new(), duration: None }
+    }
+
+    #[test]
+    fn detects_the_changed_days_and_their_weeks() {
+        init();
+        let previous = course(&[("2024-09-02", &["SQL"]), ("2024-09-03", &["Git"]), ("2024-09-16", &["Rust"])]);
+        let current = course(&[("2024-09-02", &["SQL", "Joins"]), ("2024-09-10", &["Linux"]), ("2024-09-16", &["Rust"])]);
+        let previous_attendances = [attendance("2024-09-17", AttendanceStatus::Present)];
+        let attendances = [attendance("2024-09-17", AttendanceStatus::Sick)];
+
+        let report = detect_changes(&previous, &previous_attendances, &current, &attendances).unwrap();
+
+        let days: Vec<(NaiveDate, bool, bool, bool)> = report.days.iter()
+            .map(|day| (day.date, day.kind == ChangeKind::Added, day.kind == ChangeKind::Removed, day.kind == ChangeKind::Modified))
+            .collect();
+        assert_eq!(days, [
+            (date("2024-09-02"), false, false, true),
+            (date("2024-09-03"), false, true, false),
+            (date("2024-09-10"), true, false, false),
+        ]);
+        assert_eq!(report.days[0].added_activities, ["Joins"]);
+        assert!(report.days[0].removed_activities.is_empty());
+        assert_eq!(report.days[1].removed_activities, ["Git"]);
+        assert_eq!(report.days[2].added_activities, ["Linux"]);
+
+        assert_eq!(report.attendances.len(), 1);
+        assert_eq!(report.attendances[0].date, date("2024-09-17"));
+
+        // The attendance change affects the week of 16.09. although its classbook day is unchanged,
+        // the added week 37 lies between the generated weeks and renumbers the following ones
+        assert_eq!(report.weeks, [
+            "Nr. 1 (02.09.24 - 03.09.24)",
+            "New week 37 of 2024, the following weeks would be renumbered",
+            "Nr. 2 (16.09.24 - 16.09.24)",
+        ]);
+    }
+
+    #[test]
+    fn renders_the_changes_as_markdown() {
+        init();
+        let previous = course(&[("2024-09-02", &["SQL"])]);
+        let current = course(&[("2024-09-02", &["Joins"])]);
+
+        let report = detect_changes(&previous, &[], &current, &[attendance("2024-09-02", AttendanceStatus::Late)]).unwrap();
+        let markdown = report.to_markdown("2024-09-03 08:00");
+
+        assert!(markdown.starts_with("# Changes since the last run (2024-09-03 08:00)\n"));
+        assert!(markdown.contains("### FIAE Fachinformatiker – 02.09.24 (modified)\n\n- Added: Joins\n- Removed: SQL\n- Old text: SQL\n- New text: Joins\n"), "{}", markdown);
+        assert!(markdown.contains("## Attendance\n\n- 02.09.2024: none → no times ("), "{}", markdown);
+        assert!(markdown.contains("## Affected report weeks\n\n- Nr. 1 (02.09.24 - 02.09.24)\n"), "{}", markdown);
+    }
+
+    #[test]
+    fn reports_nothing_for_the_same_snapshot() {
+        init();
+        let courses = course(&[("2024-09-02", &["SQL"])]);
+        let attendances = [attendance("2024-09-02", AttendanceStatus::Present)];
+
+        let report = detect_changes(&courses, &attendances, &courses, &attendances).unwrap();
+
+        assert!(report.is_empty());
+        assert!(report.weeks.is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use crate::common::tables::DateRange;
+use crate::export::dataset::ExportFormat;
+
+// Command line arguments
+#[derive(Parser)]
+#[command(version, about = "Creates a report book from the GFN Lernplattform")]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Configuration file (default: $XDG_CONFIG_HOME/moodle-report-portfolio/config.toml, then ./config.toml)
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    // The flags of the run without a subcommand, which scrapes and renders in one go
+
+    /// Store every request/response pair in this directory (secrets are scrubbed)
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Render from the local store without any network access
+    #[arg(long, conflicts_with_all = ["record", "replay"])]
+    pub offline: bool,
+
+    /// Serve all responses from a recorded directory without any network access
+    #[arg(long, value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+
+    /// Render from a JSON dataset (see --export json) instead of scraping
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["record", "replay", "offline"])]
+    pub import: Option<PathBuf>,
+
+    /// Export the scraped data instead of creating the report book
+    #[arg(long, value_name = "FORMAT")]
+    pub export: Option<ExportFormat>,
+
+    /// Output file of the export (default: Reports.<format>)
+    #[arg(long, value_name = "PATH", requires = "export")]
+    pub output: Option<PathBuf>,
+
+    /// Fetch the settled courses of the local store again, so every change since the last run is detected
+    #[arg(long, conflicts_with_all = ["offline", "import"])]
+    pub full: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Log in (or check the stored session) and keep the session for the next runs
+    Login {
+        #[command(flatten)]
+        network: NetworkArgs,
+    },
+    /// Fetch the courses and the attendance into the local store and a JSON dataset
+    Scrape {
+        #[command(flatten)]
+        network: NetworkArgs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Dataset file (default: dataset.json)
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// Fetch the settled courses of the local store again, so every change since the last run is detected
+        #[arg(long)]
+        full: bool,
+    },
+    /// Create the report book from a dataset or the local store, without any network access
+    Render {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Report book file (default: Reports.xlsx)
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Export a dataset or the local store as JSON, NDJSON or CSV
+    Export {
+        /// Format of the export
+        #[arg(long, value_name = "FORMAT")]
+        format: ExportFormat,
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Output file (default: Reports.<format>)
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Check the configuration, the password source, the local files and the connection to the platform
+    Doctor,
+    /// Write a commented starter configuration interactively
+    Init {
+        /// Overwrite an existing configuration file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Manage the encrypted vault of the session cookies and the password
+    Vault {
+        #[command(subcommand)]
+        action: VaultAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum VaultAction {
+    /// Re-encrypt the vault with a new passphrase
+    Rotate,
+    /// Overwrite and delete the vault
+    Wipe,
+    /// Store the account password in the vault (an empty input removes it)
+    SetPassword,
+}
+
+// Flags of the commands that talk to the platform
+#[derive(clap::Args)]
+pub struct NetworkArgs {
+    /// Store every request/response pair in this directory (secrets are scrubbed)
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Serve all responses from a recorded directory without any network access
+    #[arg(long, value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+}
+
+// Flags of the commands that read scraped data
+#[derive(clap::Args)]
+pub struct SourceArgs {
+    /// JSON dataset written by scrape or export (default: the local store)
+    #[arg(long, value_name = "FILE")]
+    pub input: Option<PathBuf>,
+}
+
+// Flags that narrow the data down to some days and courses, `scrape` applies them to the output only: every course is still fetched
+// and synced into the local store
+#[derive(clap::Args)]
+pub struct FilterArgs {
+    /// Only days on or after this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    pub from: Option<NaiveDate>,
+
+    /// Only days on or before this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    pub to: Option<NaiveDate>,
+
+    /// Only courses whose name or id contains this text, can be repeated
+    #[arg(long = "course", value_name = "NAME")]
+    pub courses: Vec<String>,
+}
+
+impl FilterArgs {
+    pub fn date_range(&self) -> DateRange {
+        DateRange { from: self.from, to: self.to }
+    }
+}
@@ -1,73 +1,320 @@
-use serde_derive::Deserialize;
-use std::fs::File;
-use std::io::Read;
-use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fs;
 use std::error::Error;
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use chrono::NaiveDate;
+use log::{info, warn};
+use toml::{Table, Value};
+use crate::common::tables::AttendanceStatus;
+use crate::utils::validation::{deserialize_problem, format_problems, validate_config};
 
 // Define the configuration file name
 const CONFIG_FILE_NAME: &str = "config.toml";
+const CONFIG_DIR_NAME: &str = "moodle-report-portfolio";
 
-// Function to generate the list of possible configuration file paths
+// Environment variables like MRP_ACCOUNT__PASSWORD override the key password in the [account] section
+const ENV_PREFIX: &str = "MRP_";
+const ENV_SEPARATOR: &str = "__";
+
+// Format of the dates in the configuration file
+pub const CONFIG_DATE_FORMAT: &str = "%Y-%m-%d";
+
+// The configuration file given with --config, it replaces the lookup chain
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+// Sets the configuration file given on the command line, must be called before CONFIG is used
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+// Function to generate the list of possible configuration file paths, in lookup order:
+// --config, $XDG_CONFIG_HOME/moodle-report-portfolio (or ~/.config), the working directory and its parents
 fn generate_config_file_paths() -> Vec<PathBuf> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return vec![path.clone()];
+    }
+
     let mut paths = Vec::new();
 
-    #[cfg(target_os = "macos")]
-    {
-        paths.push(PathBuf::from("./"));
-        paths.push(PathBuf::from("../"));
-        paths.push(PathBuf::from("../../"));
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join(CONFIG_DIR_NAME));
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        paths.push(PathBuf::from(".\\"));
-        paths.push(PathBuf::from("..\\"));
-        paths.push(PathBuf::from("..\\..\\"));
+    paths.push(PathBuf::from("."));
+    paths.push(PathBuf::from(".."));
+    paths.push(PathBuf::from("..").join(".."));
+
+    paths.into_iter().map(|path| path.join(CONFIG_FILE_NAME)).collect()
+}
+
+// The file written by the init wizard: the --config path or the XDG location, the working directory without a home directory
+pub fn default_config_path() -> PathBuf {
+    generate_config_file_paths().into_iter().next().unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME))
+}
+
+// Applies the MRP_* environment variables to the parsed configuration file and returns the overridden keys with their variable
+// A value keeps the type of the key in the file, new keys are strings until the schema asks for another type (see deserialize_config)
+// Other variables with the prefix are not meant for the scraper, a name that is no MRP_SECTION__KEY path is skipped with a warning
+// Shorter paths go first, so a variable below a value (MRP_ACCOUNT__PASSWORD__X) cannot turn it into a section
+fn apply_env_overrides(table: &mut Table, vars: impl Iterator<Item = (String, String)>) -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String, Vec<String>)> = vars
+        .filter_map(|(name, raw)| {
+            let segments = name.strip_prefix(ENV_PREFIX)?.split(ENV_SEPARATOR).map(|segment| segment.to_lowercase()).collect();
+            Some((name, raw, segments))
+        })
+        .collect();
+    vars.sort_by_key(|(_, _, segments)| segments.len());
+
+    let mut overrides = Vec::new();
+    for (name, raw, segments) in vars {
+
+        if segments.len() < 2 || segments.iter().any(|segment| segment.is_empty()) {
+            warn!("Ignoring the environment variable {}, it is no {}SECTION{}KEY override", name, ENV_PREFIX, ENV_SEPARATOR);
+            continue;
+        }
+
+        let (leaf, sections) = segments.split_last().unwrap();
+        if !is_overridable(table, sections, leaf) {
+            warn!("Ignoring the environment variable {}, {} is not a value of the configuration", name, segments.join("."));
+            continue;
+        }
+
+        let mut current = &mut *table;
+        for section in sections {
+            current = current.entry(section.clone()).or_insert_with(|| Value::Table(Table::new())).as_table_mut().unwrap();
+        }
+
+        let value = match current.get(leaf) {
+            Some(Value::String(_)) | None => Value::String(raw),
+            Some(_) => parse_env_value(&raw),
+        };
+        current.insert(leaf.clone(), value);
+        overrides.push((segments.join("."), name));
     }
-    
-    paths.into_iter().map(|mut path| {
-        path.push(CONFIG_FILE_NAME);
-        path
-    }).collect()
+
+    overrides
 }
 
-// Possible locations for the configuration file
-lazy_static! {
-    pub static ref FILES: Vec<PathBuf> = generate_config_file_paths();
+// Whether the sections are (or can become) tables and the leaf is a value, not a whole section
+fn is_overridable(table: &Table, sections: &[String], leaf: &str) -> bool {
+    let mut current = table;
+    for section in sections {
+        match current.get(section) {
+            Some(Value::Table(inner)) => current = inner,
+            Some(_) => return false,
+            None => return true,
+        }
+    }
+
+    !matches!(current.get(leaf), Some(Value::Table(_)))
 }
 
-// Function to check if the configuration file exists
-pub fn config_exists(paths: &[PathBuf]) -> bool {
-    paths.iter().any(|path| Path::new(path).exists())
+// Parses and validates a configuration file with the environment overrides, returns the configuration, the file and the overrides
+// Every problem is reported with its file and line (or its environment variable) and a suggestion
+fn parse_config(path: &Path, contents: &str, vars: impl Iterator<Item = (String, String)>) -> Result<(GlobalConfig, Table, Vec<(String, String)>), Box<dyn Error>> {
+    let file: Table = toml::from_str(contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut table = file.clone();
+    let mut overrides = apply_env_overrides(&mut table, vars);
+
+    let config = deserialize_config(table, &file, &mut overrides)
+        .map_err(|e| format_problems(&[deserialize_problem(&e)], path, contents, &overrides))?;
+
+    let problems = validate_config(&config);
+    if !problems.is_empty() {
+        return Err(format_problems(&problems, path, contents, &overrides).into());
+    }
+
+    Ok((config, file, overrides))
+}
+
+// Checks a configuration file on its own, without the environment, e.g. the one written by the init wizard
+pub fn check_config(path: &Path, contents: &str) -> Result<(), Box<dyn Error>> {
+    parse_config(path, contents, std::iter::empty()).map(|_| ())
+}
+
+// Deserializes the configuration, a new key from the environment that the schema does not take as a string is parsed as a TOML value
+// and the deserialization is retried, so "2024-09-01" or "123456" stay strings where strings are expected
+// An override of a key or section the schema does not know is dropped with a warning, an unknown key in the file is still an error
+fn deserialize_config(mut table: Table, file: &Table, overrides: &mut Vec<(String, String)>) -> Result<GlobalConfig, serde_path_to_error::Error<toml::de::Error>> {
+    loop {
+        let error = match serde_path_to_error::deserialize(Value::Table(table.clone())) {
+            Ok(config) => return Ok(config),
+            Err(error) => error,
+        };
+
+        let key = error.path().to_string();
+        if overrides.iter().any(|(overridden, _)| *overridden == key) {
+            if let Some(value @ Value::String(_)) = value_mut(&mut table, &key) {
+                let parsed = parse_env_value(value.as_str().unwrap());
+                if !parsed.is_str() {
+                    *value = parsed;
+                    continue;
+                }
+            }
+        }
+
+        let section = format!("{}.", key);
+        let from_env = |overridden: &String| *overridden == key || overridden.starts_with(&section);
+        if error.inner().message().starts_with("unknown field") && !contains_key(file, &key) && overrides.iter().any(|(overridden, _)| from_env(overridden)) {
+            remove_key(&mut table, &key);
+            overrides.retain(|(overridden, name)| {
+                if from_env(overridden) {
+                    warn!("Ignoring the environment variable {}, {} is not a configuration key", name, overridden);
+                }
+                !from_env(overridden)
+            });
+            continue;
+        }
+
+        return Err(error);
+    }
+}
+
+fn parse_env_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+// The dotted keys of all values, e.g. "account.user_name"
+fn collect_keys(value: &Value, prefix: &str, keys: &mut Vec<String>) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                collect_keys(value, &path, keys);
+            }
+        }
+        _ => keys.push(prefix.to_string()),
+    }
+}
+
+fn value_mut<'a>(table: &'a mut Table, key: &str) -> Option<&'a mut Value> {
+    let (sections, leaf) = match key.rsplit_once('.') {
+        Some((sections, leaf)) => (sections.split('.').collect(), leaf),
+        None => (Vec::new(), key),
+    };
+
+    let mut current = table;
+    for section in sections {
+        current = current.get_mut(section)?.as_table_mut()?;
+    }
+
+    current.get_mut(leaf)
+}
+
+fn remove_key(table: &mut Table, key: &str) -> Option<Value> {
+    match key.rsplit_once('.') {
+        Some((sections, leaf)) => value_mut(table, sections)?.as_table_mut()?.remove(leaf),
+        None => table.remove(key),
+    }
+}
+
+fn contains_key(table: &Table, key: &str) -> bool {
+    let mut current = table;
+    let mut segments = key.split('.').peekable();
+
+    while let Some(segment) = segments.next() {
+        match (current.get(segment), segments.peek()) {
+            (Some(Value::Table(inner)), Some(_)) => current = inner,
+            (Some(_), None) => return true,
+            _ => return false,
+        }
+    }
+
+    false
+}
+
+// Logs where every value of the configuration came from: the environment, the configuration file or the defaults
+fn log_config_sources(config: &GlobalConfig, file: &Table, path: &Path, overrides: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+    let mut keys = Vec::new();
+    collect_keys(&Value::try_from(config)?, "", &mut keys);
+
+    let (mut from_env, mut from_file, mut from_defaults) = (Vec::new(), Vec::new(), Vec::new());
+    for key in keys {
+        if let Some((_, name)) = overrides.iter().find(|(overridden, _)| *overridden == key) {
+            from_env.push(format!("{} ({})", key, name));
+        } else if contains_key(file, &key) {
+            from_file.push(key);
+        } else {
+            from_defaults.push(key);
+        }
+    }
+
+    info!("Loaded the configuration from {}", path.display());
+    if !from_env.is_empty() {
+        info!("Config values from the environment: {}", from_env.join(", "));
+    }
+    info!("Config values from {}: {}", path.display(), from_file.join(", "));
+    if !from_defaults.is_empty() {
+        info!("Config values from the defaults: {}", from_defaults.join(", "));
+    }
+
+    Ok(())
 }
 
 // Struct to hold the configuration
-#[derive(Eq, PartialEq, Hash, Clone, Deserialize)]
+#[derive(PartialEq, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct GlobalConfig {
     pub account: AccountConfig,
     pub company: CompanyConfig,
     pub signature: SignatureConfig,
     pub website: WebsiteConfig,
     pub options: OptionsSettings,
+    #[serde(default)]
+    pub store: StoreConfig,
+    #[serde(default)]
+    pub report: ReportConfig,
+    #[serde(default)]
+    pub training: TrainingConfig,
+    #[serde(default)]
+    pub calendar: CalendarConfig,
+    #[serde(default)]
+    pub hours: HoursConfig,
+    #[serde(default)]
+    pub attendance: AttendanceConfig,
+    #[serde(default)]
+    pub absence: AbsenceConfig,
+    #[serde(default)]
+    pub vault: VaultConfig,
 }
 
 // Struct to hold the account configuration
-#[derive(Eq, PartialEq, Hash, Clone, Deserialize)]
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct AccountConfig {
     pub user_name: String,
+    #[serde(default)]
     pub password: String,
+    #[serde(default)]
+    pub password_env: String,
+    #[serde(default)]
+    pub password_command: String,
+    #[serde(default)]
+    pub password_file: String,
 }
 
 // Struct to hold the company configuration
-#[derive(Eq, PartialEq, Hash, Clone, Deserialize)]
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct CompanyConfig {
     pub educator_name: String,
     pub location: String,
 }
 
 // Struct to hold the signature configuration
-#[derive(Eq, PartialEq, Hash, Clone, Deserialize)]
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct SignatureConfig {
     pub signature: String,
     pub font_name: String,
@@ -75,65 +322,348 @@ pub struct SignatureConfig {
 }
 
 // Struct to hold the website configuration
-#[derive(Eq, PartialEq, Hash, Clone, Deserialize)]
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct WebsiteConfig {
     pub base_url: String,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub token: String,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub retry_max_delay_ms: u64,
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: u32,
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+// Default values for the retry settings
+fn default_max_attempts() -> u32 { 4 }
+fn default_retry_base_delay_ms() -> u64 { 500 }
+fn default_retry_max_delay_ms() -> u64 { 30_000 }
+fn default_request_timeout_secs() -> u64 { 30 }
+
+// Default values for the request limiter
+fn default_requests_per_second() -> u32 { 5 }
+fn default_max_in_flight() -> usize { 4 }
+
+// Enum to select the data source for courses, classbooks and attendance
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Scrape,
+    WebService,
 }
 
 // Struct to hold the configuration
-#[derive(Eq, PartialEq, Hash, Clone, Deserialize)]
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct OptionsSettings {
     pub test_mode: bool,
 }
 
-pub struct Config {
-    config: GlobalConfig,
+// Struct to hold the local store configuration
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StoreConfig {
+    #[serde(default = "default_store_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_store_path")]
+    pub path: String,
+    #[serde(default = "default_refresh_days")]
+    pub refresh_days: i64,
+    #[serde(default = "default_recheck_days")]
+    pub recheck_days: i64,
+    #[serde(default = "default_changes_report")]
+    pub changes_report: String,
+}
+
+// Default values for the local store
+fn default_store_enabled() -> bool { true }
+fn default_store_path() -> String { "portfolio.db".to_string() }
+fn default_refresh_days() -> i64 { 14 }
+fn default_recheck_days() -> i64 { 30 }
+fn default_changes_report() -> String { "Changes.md".to_string() }
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        StoreConfig {
+            enabled: default_store_enabled(),
+            path: default_store_path(),
+            refresh_days: default_refresh_days(),
+            recheck_days: default_recheck_days(),
+            changes_report: default_changes_report(),
+        }
+    }
+}
+
+// Struct to hold the report book configuration
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportConfig {
+    #[serde(default = "default_overlay")]
+    pub overlay: String,
+    #[serde(default)]
+    pub group_by_learning_field: bool,
+}
+
+// Default values for the report book
+fn default_overlay() -> String { "overlay.toml".to_string() }
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        ReportConfig {
+            overlay: default_overlay(),
+            group_by_learning_field: false,
+        }
+    }
+}
+
+// Struct to hold the training period configuration
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrainingConfig {
+    #[serde(default)]
+    pub start_date: String,
+    #[serde(default)]
+    pub end_date: String,
+    #[serde(default = "default_training_text")]
+    pub default_text: String,
+}
+
+// Default values for the training period
+fn default_training_text() -> String { "Betriebliche Tätigkeit".to_string() }
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        TrainingConfig {
+            start_date: String::new(),
+            end_date: String::new(),
+            default_text: default_training_text(),
+        }
+    }
+}
+
+// Struct to hold the encrypted vault configuration
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VaultConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_vault_path")]
+    pub path: String,
+    #[serde(default)]
+    pub passphrase_env: String,
+}
+
+// Default values for the encrypted vault
+fn default_vault_path() -> String { "vault.json".to_string() }
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        VaultConfig {
+            enabled: false,
+            path: default_vault_path(),
+            passphrase_env: String::new(),
+        }
+    }
+}
+
+// Struct to hold the attendance scraping configuration
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AttendanceConfig {
+    #[serde(default)]
+    pub cutoff_date: String,
+}
+
+impl Default for AttendanceConfig {
+    fn default() -> Self {
+        AttendanceConfig {
+            cutoff_date: String::new(),
+        }
+    }
+}
+
+// Struct to hold the holiday calendar configuration
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CalendarConfig {
+    #[serde(default = "default_holidays")]
+    pub holidays: bool,
+    #[serde(default)]
+    pub state: Option<FederalState>,
+}
+
+// Default values for the holiday calendar
+fn default_holidays() -> bool { true }
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        CalendarConfig {
+            holidays: default_holidays(),
+            state: None,
+        }
+    }
+}
+
+// Enum of the German federal states, used for the regional public holidays
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum FederalState {
+    BW, // Baden-Württemberg
+    BY, // Bayern
+    BE, // Berlin
+    BB, // Brandenburg
+    HB, // Bremen
+    HH, // Hamburg
+    HE, // Hessen
+    MV, // Mecklenburg-Vorpommern
+    NI, // Niedersachsen
+    NW, // Nordrhein-Westfalen
+    RP, // Rheinland-Pfalz
+    SL, // Saarland
+    SN, // Sachsen
+    ST, // Sachsen-Anhalt
+    SH, // Schleswig-Holstein
+    TH, // Thüringen
 }
 
-// Function to attempt opening the first available configuration file from a list of paths
-fn open_first_available_file(paths: &[PathBuf]) -> std::io::Result<File> {
-    let mut errors = Vec::new();
+// Struct to hold the hour computation configuration
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoursConfig {
+    #[serde(default = "default_hours")]
+    pub default_hours: u32,
+    #[serde(default)]
+    pub rounding: Rounding,
+    #[serde(default = "default_breaks")]
+    pub breaks: Vec<BreakRule>,
+}
+
+// A break that is deducted once the worked time exceeds after_minutes
+#[derive(Eq, PartialEq, Hash, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BreakRule {
+    pub after_minutes: u32,
+    pub minutes: u32,
+}
 
-    for path in paths {
-        match File::open(&path) {
-            Ok(file) => return Ok(file),
-            Err(e) => errors.push((path.clone(), e)),
+// Enum to select how the minutes of a day are rounded
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    #[default]
+    Quarter,
+    Hour,
+}
+
+// Default values for the hour computation, the breaks follow the German Arbeitszeitgesetz
+fn default_hours() -> u32 { 8 }
+fn default_breaks() -> Vec<BreakRule> {
+    vec![
+        BreakRule { after_minutes: 360, minutes: 30 },
+        BreakRule { after_minutes: 540, minutes: 45 },
+    ]
+}
+
+impl Default for HoursConfig {
+    fn default() -> Self {
+        HoursConfig {
+            default_hours: default_hours(),
+            rounding: Rounding::default(),
+            breaks: default_breaks(),
         }
     }
+}
 
-    let error_message = errors
-        .into_iter()
-        .map(|(path, error)| format!("{}: {}", path.display(), error))
-        .collect::<Vec<String>>()
-        .join("\n");
+// Struct to hold the absence policies, one per attendance status that means the trainee missed the day
+#[derive(PartialEq, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AbsenceConfig {
+    #[serde(default = "default_sick_policy")]
+    pub sick: AbsencePolicy,
+    #[serde(default = "default_excused_policy")]
+    pub excused: AbsencePolicy,
+    #[serde(default = "default_vacation_policy")]
+    pub vacation: AbsencePolicy,
+    #[serde(default = "default_unexcused_policy")]
+    pub unexcused: AbsencePolicy,
+}
+
+// How an absent day is shown in the report book, "{remarks}" in the text is replaced with the remarks of the record
+#[derive(PartialEq, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AbsencePolicy {
+    pub text: String,
+    #[serde(default)]
+    pub hours: f64,
+    #[serde(default)]
+    pub keep_activities: bool,
+    #[serde(default)]
+    pub highlight: bool,
+}
 
-    Err(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        format!("No configuration file found:\n{}", error_message),
-    ))
+// Default values for the absence policies
+fn absence_policy(text: &str) -> AbsencePolicy {
+    AbsencePolicy { text: text.to_string(), hours: 0.0, keep_activities: false, highlight: false }
+}
+fn default_sick_policy() -> AbsencePolicy { absence_policy("Keine Teilnahme am Unterricht aus gesundheitlichen Gründen") }
+fn default_excused_policy() -> AbsencePolicy { absence_policy("Entschuldigt abwesend") }
+fn default_vacation_policy() -> AbsencePolicy { absence_policy("Urlaub") }
+fn default_unexcused_policy() -> AbsencePolicy { absence_policy("Keine Teilnahme am Unterricht ({remarks})") }
+
+impl Default for AbsenceConfig {
+    fn default() -> Self {
+        AbsenceConfig {
+            sick: default_sick_policy(),
+            excused: default_excused_policy(),
+            vacation: default_vacation_policy(),
+            unexcused: default_unexcused_policy(),
+        }
+    }
+}
+
+pub struct Config {
+    config: GlobalConfig,
+    path: PathBuf,
 }
 
 impl Config {
     // Constructor for the Config struct. It reads the configuration file and creates an instance of the struct
     pub fn new() -> Result<Config, Box<dyn Error>> {
-        if !config_exists(&FILES) {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Configuration file not found.",
-            )));
-        }
+        let paths = generate_config_file_paths();
+        let path = paths.iter().find(|path| path.exists()).ok_or_else(|| {
+            let searched = paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
+            format!("Configuration file not found, searched: {}\nRun `moodle-report-portfolio init` to create one", searched)
+        })?;
 
-        let mut file = open_first_available_file(&FILES)?;
-        let mut contents = String::new();
+        let contents = fs::read_to_string(path)?;
 
-        file.read_to_string(&mut contents)?;
-        let config: GlobalConfig = toml::from_str(&contents)?;
+        // Variables that are not valid UTF-8 cannot be configuration values and are skipped
+        let vars = env::vars_os().filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+        let (config, file, overrides) = parse_config(path, &contents, vars)?;
+
+        log_config_sources(&config, &file, path, &overrides)?;
 
         Ok(Config {
             config,
+            path: path.clone(),
         })
     }
 
+    // Getter for the path of the loaded configuration file
+    pub fn get_config_path(&self) -> &Path {
+        &self.path
+    }
+
     // Getter for the user_name field
     pub fn get_user_name(&self) -> &str {
         &self.config.account.user_name
@@ -144,6 +674,21 @@ impl Config {
         &self.config.account.password
     }
 
+    // Getter for the password_env field
+    pub fn get_password_env(&self) -> &str {
+        &self.config.account.password_env
+    }
+
+    // Getter for the password_command field
+    pub fn get_password_command(&self) -> &str {
+        &self.config.account.password_command
+    }
+
+    // Getter for the password_file field
+    pub fn get_password_file(&self) -> &str {
+        &self.config.account.password_file
+    }
+
     // Getter for the educator_name field
     pub fn get_educator_name(&self) -> &str {
         &self.config.company.educator_name
@@ -174,13 +719,234 @@ impl Config {
         &self.config.website.base_url
     }
 
+    // Getter for the backend field
+    pub fn get_backend(&self) -> Backend {
+        self.config.website.backend
+    }
+
+    // Getter for the token field
+    pub fn get_token(&self) -> &str {
+        &self.config.website.token
+    }
+
+    // Getter for the max_attempts field
+    pub fn get_max_attempts(&self) -> u32 {
+        self.config.website.max_attempts
+    }
+
+    // Getter for the retry_base_delay_ms field
+    pub fn get_retry_base_delay_ms(&self) -> u64 {
+        self.config.website.retry_base_delay_ms
+    }
+
+    // Getter for the retry_max_delay_ms field
+    pub fn get_retry_max_delay_ms(&self) -> u64 {
+        self.config.website.retry_max_delay_ms
+    }
+
+    // Getter for the request_timeout_secs field
+    pub fn get_request_timeout_secs(&self) -> u64 {
+        self.config.website.request_timeout_secs
+    }
+
+    // Getter for the requests_per_second field
+    pub fn get_requests_per_second(&self) -> u32 {
+        self.config.website.requests_per_second
+    }
+
+    // Getter for the max_in_flight field
+    pub fn get_max_in_flight(&self) -> usize {
+        self.config.website.max_in_flight
+    }
+
+    // Getter for the store enabled field
+    pub fn is_store_enabled(&self) -> bool {
+        self.config.store.enabled
+    }
+
+    // Getter for the store path field
+    pub fn get_store_path(&self) -> &str {
+        &self.config.store.path
+    }
+
+    // Getter for the refresh_days field
+    pub fn get_refresh_days(&self) -> i64 {
+        self.config.store.refresh_days
+    }
+
+    // Getter for the recheck_days field
+    pub fn get_recheck_days(&self) -> i64 {
+        self.config.store.recheck_days
+    }
+
+    // Getter for the changes_report field
+    pub fn get_changes_report(&self) -> &str {
+        &self.config.store.changes_report
+    }
+
+    // Getter for the vault enabled field
+    pub fn is_vault_enabled(&self) -> bool {
+        self.config.vault.enabled
+    }
+
+    // Getter for the vault path field
+    pub fn get_vault_path(&self) -> &str {
+        &self.config.vault.path
+    }
+
+    // Getter for the passphrase_env field
+    pub fn get_vault_passphrase_env(&self) -> &str {
+        &self.config.vault.passphrase_env
+    }
+
+    // Getter for the overlay field
+    pub fn get_overlay_path(&self) -> &str {
+        &self.config.report.overlay
+    }
+
+    // Getter for the group_by_learning_field field
+    pub fn is_group_by_learning_field(&self) -> bool {
+        self.config.report.group_by_learning_field
+    }
+
+    // Getter for the training start_date field
+    pub fn get_training_start_date(&self) -> &str {
+        &self.config.training.start_date
+    }
+
+    // Getter for the training end_date field
+    pub fn get_training_end_date(&self) -> &str {
+        &self.config.training.end_date
+    }
+
+    // Getter for the training default_text field
+    pub fn get_training_default_text(&self) -> &str {
+        &self.config.training.default_text
+    }
+
+    // Getter for the attendance cutoff_date field, None if it is empty (the date was checked when the configuration was loaded)
+    pub fn get_attendance_cutoff_date(&self) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(&self.config.attendance.cutoff_date, CONFIG_DATE_FORMAT).ok()
+    }
+
+    // Getter for the calendar holidays field
+    pub fn is_holidays_enabled(&self) -> bool {
+        self.config.calendar.holidays
+    }
+
+    // Getter for the calendar state field
+    pub fn get_state(&self) -> Option<FederalState> {
+        self.config.calendar.state
+    }
+
+    // Getter for the hours default_hours field
+    pub fn get_default_hours(&self) -> u32 {
+        self.config.hours.default_hours
+    }
+
+    // Getter for the hours rounding field
+    pub fn get_rounding(&self) -> Rounding {
+        self.config.hours.rounding
+    }
+
+    // Getter for the hours breaks field
+    pub fn get_break_rules(&self) -> &[BreakRule] {
+        &self.config.hours.breaks
+    }
+
+    // Getter for the absence policy of an attendance status, present and late days have none
+    pub fn get_absence_policy(&self, status: AttendanceStatus) -> Option<&AbsencePolicy> {
+        match status {
+            AttendanceStatus::Sick => Some(&self.config.absence.sick),
+            AttendanceStatus::Excused => Some(&self.config.absence.excused),
+            AttendanceStatus::Vacation => Some(&self.config.absence.vacation),
+            AttendanceStatus::Absent => Some(&self.config.absence.unexcused),
+            AttendanceStatus::Present | AttendanceStatus::Late => None,
+        }
+    }
+
     // Getter for the test_mode field
     pub fn get_test_mode(&self) -> bool {
         self.config.options.test_mode
     }
 }
 
-// Lazy_static is used to initialize the configuration only once and share it across the application
-lazy_static! {
-    static ref CONFIG: Config = Config::new().unwrap();
+// The configuration is loaded once at startup and shared across the application
+static LOADED_CONFIG: OnceLock<Config> = OnceLock::new();
+
+// Loads the configuration, must be called before CONFIG is used
+pub fn load_config() -> Result<(), Box<dyn Error>> {
+    let config = Config::new()?;
+    let _ = LOADED_CONFIG.set(config);
+    Ok(())
+}
+
+// Loads the configuration of the tests, they share the process so only the first call counts
+#[cfg(test)]
+pub fn load_test_config(table: Table) -> Result<(), Box<dyn Error>> {
+    let config: GlobalConfig = Value::Table(table).try_into()?;
+    let _ = LOADED_CONFIG.set(Config { config, path: PathBuf::from(CONFIG_FILE_NAME) });
+    Ok(())
+}
+
+// Shared access to the loaded configuration
+pub struct SharedConfig;
+
+impl Deref for SharedConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        LOADED_CONFIG.get().expect("The configuration is used before it was loaded")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn override_config(removed: &[(&str, &str)], vars: &[(&str, &str)]) -> GlobalConfig {
+        let mut table: Table = toml::from_str(include_str!("../../config.toml")).unwrap();
+        for (section, key) in removed {
+            table.get_mut(*section).and_then(Value::as_table_mut).unwrap().remove(*key);
+        }
+
+        let vars = vars.iter().map(|(name, value)| (name.to_string(), value.to_string()));
+        let file = table.clone();
+        let mut overrides = apply_env_overrides(&mut table, vars);
+        deserialize_config(table, &file, &mut overrides).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn takes_the_type_of_a_new_key_from_the_schema() {
+        let config = override_config(
+            &[("account", "password"), ("training", "start_date"), ("website", "max_attempts")],
+            &[("MRP_ACCOUNT__PASSWORD", "123456"), ("MRP_TRAINING__START_DATE", "2024-09-01"), ("MRP_WEBSITE__MAX_ATTEMPTS", "7")],
+        );
+
+        assert_eq!(config.account.password, "123456");
+        assert_eq!(config.training.start_date, "2024-09-01");
+        assert_eq!(config.website.max_attempts, 7);
+    }
+
+    #[test]
+    fn keeps_the_type_of_a_key_in_the_file() {
+        let config = override_config(&[], &[("MRP_ACCOUNT__PASSWORD", "123456"), ("MRP_STORE__REFRESH_DAYS", "7")]);
+
+        assert_eq!(config.account.password, "123456");
+        assert_eq!(config.store.refresh_days, 7);
+    }
+
+    #[test]
+    fn ignores_variables_that_are_not_configuration_keys() {
+        let config = override_config(&[], &[
+            ("MRP_ACCOUNT", "x"),
+            ("MRP_FOO", "1"),
+            ("MRP_FOO__BAR", "1"),
+            ("MRP_ACCOUNT__BOGUS", "1"),
+            ("MRP_ACCOUNT__PASSWORD__HASH", "1"),
+            ("MRP_ACCOUNT__PASSWORD", "123456"),
+        ]);
+
+        assert_eq!(config.account.password, "123456");
+    }
 }
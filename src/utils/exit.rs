@@ -0,0 +1,58 @@
+use std::fmt::{self, Display};
+use std::process::ExitCode;
+
+// The classes of failures, each one has its own exit code so scripts can tell them apart (clap exits with 2 on usage errors)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureClass {
+    // The configuration is missing or invalid
+    Config,
+    // The login, the password or the vault passphrase was rejected
+    Auth,
+    // The platform could not be reached or returned unexpected pages
+    Network,
+    // The local store or a dataset could not be read or written
+    Data,
+    // The report book or an export could not be written
+    Output,
+}
+
+impl FailureClass {
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            FailureClass::Config => 3,
+            FailureClass::Auth => 4,
+            FailureClass::Network => 5,
+            FailureClass::Data => 6,
+            FailureClass::Output => 7,
+        })
+    }
+}
+
+// A failed command with the class that decides its exit code
+pub struct Failure {
+    pub class: FailureClass,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(class: FailureClass, message: impl Into<String>) -> Failure {
+        Failure { class, message: message.into() }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// Turns any error into a failure of the given class, prefixed with what was attempted
+pub trait OrFail<T> {
+    fn or_fail(self, class: FailureClass, context: &str) -> Result<T, Failure>;
+}
+
+impl<T, E: Display> OrFail<T> for Result<T, E> {
+    fn or_fail(self, class: FailureClass, context: &str) -> Result<T, Failure> {
+        self.map_err(|e| Failure::new(class, format!("{}: {}", context, e)))
+    }
+}
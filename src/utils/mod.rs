@@ -1,3 +1,9 @@
+pub mod args;
 pub mod config;
+pub mod exit;
 pub mod logger;
-pub mod replacement;
\ No newline at end of file
+pub mod replacement;
+pub mod validation;
+pub mod wizard;
+#[cfg(test)]
+pub mod testing;
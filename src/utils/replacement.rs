@@ -66,7 +66,6 @@ pub async fn apply_replacements(description: &str) -> String {
         ("Lernmethode: Workshop in", " "),
         ("Praktische Übung:", " "),
         ("Lernmethoden:", " "),
-        ("Krank", "Selbstlernphase"),
         ("hemen und Lernziele", "Themen und Lernziele")
     ];
 
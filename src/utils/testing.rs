@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use toml::{Table, Value};
+use crate::utils::config::load_test_config;
+
+// The tests use the shipped configuration, pointed at the mock server
+const CONFIG_TEMPLATE: &str = include_str!("../../config.toml");
+pub const TEST_TOKEN: &str = "test-token";
+
+// Pages recorded with --record, replayed by the parser tests
+pub const REGRESSION_FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay");
+
+static BASE_URL: OnceLock<String> = OnceLock::new();
+static ROUTES: Mutex<Vec<Route>> = Mutex::new(Vec::new());
+
+// A canned response of the mock server
+#[derive(Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockResponse {
+    pub fn json(body: &str) -> MockResponse {
+        MockResponse { status: 200, headers: vec![("Content-Type".to_string(), "application/json".to_string())], body: body.to_string() }
+    }
+
+    pub fn status(status: u16) -> MockResponse {
+        MockResponse { status, headers: Vec::new(), body: String::new() }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> MockResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+struct Route {
+    pattern: String,
+    responses: VecDeque<MockResponse>,
+    hits: usize,
+}
+
+// Starts the mock server and loads the test configuration with the web services backend on it, returns its base URL
+// Retries are fast and the rate limit is off, every test calls it first
+pub fn init() -> &'static str {
+    BASE_URL.get_or_init(|| {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind the mock server");
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                thread::spawn(move || serve(stream));
+            }
+        });
+
+        let mut table: Table = toml::from_str(CONFIG_TEMPLATE).expect("invalid config.toml");
+        let website = table.get_mut("website").and_then(Value::as_table_mut).unwrap();
+        website.insert("base_url".to_string(), Value::String(base_url.clone()));
+        website.insert("backend".to_string(), Value::String("webservice".to_string()));
+        website.insert("token".to_string(), Value::String(TEST_TOKEN.to_string()));
+        website.insert("max_attempts".to_string(), Value::Integer(3));
+        website.insert("retry_base_delay_ms".to_string(), Value::Integer(1));
+        website.insert("retry_max_delay_ms".to_string(), Value::Integer(50));
+        website.insert("request_timeout_secs".to_string(), Value::Integer(5));
+        website.insert("requests_per_second".to_string(), Value::Integer(0));
+        load_test_config(table).expect("invalid test configuration");
+
+        base_url
+    })
+}
+
+// Answers the requests whose path and query contain `pattern` with the responses in turn, the last one is repeated
+// The patterns have to be unique across the tests, they run in parallel against the same server
+pub fn mock(pattern: &str, responses: Vec<MockResponse>) {
+    assert!(!responses.is_empty(), "a mock needs at least one response");
+
+    let mut routes = ROUTES.lock().unwrap();
+    routes.retain(|route| route.pattern != pattern);
+    routes.push(Route { pattern: pattern.to_string(), responses: responses.into(), hits: 0 });
+}
+
+// Number of requests answered for a pattern
+pub fn hits(pattern: &str) -> usize {
+    ROUTES.lock().unwrap().iter().find(|route| route.pattern == pattern).map_or(0, |route| route.hits)
+}
+
+fn serve(mut stream: TcpStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+
+    // The headers are not needed, only the requests without a body (GET) are supported
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) if line.trim().is_empty() => break,
+            Ok(_) => {}
+        }
+    }
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let response = {
+        let mut routes = ROUTES.lock().unwrap();
+        routes.iter_mut().find(|route| target.contains(&route.pattern)).map(|route| {
+            route.hits += 1;
+            if route.responses.len() > 1 { route.responses.pop_front().unwrap() } else { route.responses[0].clone() }
+        })
+    }.unwrap_or_else(|| MockResponse::status(404));
+
+    let mut head = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+}
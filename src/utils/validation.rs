@@ -0,0 +1,319 @@
+use std::path::Path;
+use chrono::NaiveDate;
+use reqwest::Url;
+use toml::de::Error as TomlError;
+use crate::utils::config::{Backend, GlobalConfig, CONFIG_DATE_FORMAT};
+
+// A problem of the configuration, the key is dotted like "account.password"
+pub struct ConfigProblem {
+    pub key: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl ConfigProblem {
+    fn new(key: &str, message: &str, suggestion: Option<String>) -> ConfigProblem {
+        ConfigProblem { key: key.to_string(), message: message.to_string(), suggestion }
+    }
+}
+
+// Checks the values that deserialize fine but would only fail later, e.g. at the login
+pub fn validate_config(config: &GlobalConfig) -> Vec<ConfigProblem> {
+    let mut problems = Vec::new();
+
+    if config.account.user_name.trim().is_empty() {
+        problems.push(ConfigProblem::new("account.user_name", "must not be empty", Some("set the user name of your Lernplattform account".to_string())));
+    }
+
+    // Without any password source the password is asked for at the login
+    let sources: Vec<&str> = [
+        ("password", &config.account.password),
+        ("password_env", &config.account.password_env),
+        ("password_command", &config.account.password_command),
+        ("password_file", &config.account.password_file),
+    ].into_iter().filter(|(_, value)| !value.is_empty()).map(|(name, _)| name).collect();
+
+    if sources.len() > 1 {
+        let key = format!("account.{}", sources[1]);
+        problems.push(ConfigProblem::new(&key, &format!("conflicts with {}, only one password source can be set", sources[0]), Some("keep the one you want to use and remove the others".to_string())));
+    }
+
+    problems.extend(validate_base_url(&config.website.base_url));
+
+    if config.website.backend == Backend::WebService && config.website.token.trim().is_empty() {
+        problems.push(ConfigProblem::new("website.token", "must not be empty for the webservice backend", Some("create a token in your Moodle profile or use backend = \"scrape\"".to_string())));
+    }
+
+    if config.vault.enabled && config.vault.path.trim().is_empty() {
+        problems.push(ConfigProblem::new("vault.path", "must not be empty when the vault is enabled", Some("e.g. path = \"vault.json\"".to_string())));
+    }
+
+    problems.extend(validate_date("attendance.cutoff_date", &config.attendance.cutoff_date));
+
+    // The training period is only used with both dates, each one is still checked on its own
+    let (start_date, end_date) = (&config.training.start_date, &config.training.end_date);
+    problems.extend(validate_date("training.start_date", start_date));
+    problems.extend(validate_date("training.end_date", end_date));
+    if let (Ok(start), Ok(end)) = (NaiveDate::parse_from_str(start_date, CONFIG_DATE_FORMAT), NaiveDate::parse_from_str(end_date, CONFIG_DATE_FORMAT)) {
+        if start > end {
+            problems.push(ConfigProblem::new("training.end_date", &format!("{} is before the start_date {}", end_date, start_date), Some("swap the dates".to_string())));
+        }
+    }
+
+    let policies = [
+        ("sick", &config.absence.sick),
+        ("excused", &config.absence.excused),
+        ("vacation", &config.absence.vacation),
+        ("unexcused", &config.absence.unexcused),
+    ];
+    for (name, policy) in policies {
+        if !(0.0..=24.0).contains(&policy.hours) {
+            problems.push(ConfigProblem::new(&format!("absence.{}.hours", name), "must be between 0 and 24", Some("e.g. hours = 7.5".to_string())));
+        }
+    }
+
+    if config.signature.font_size == 0 {
+        problems.push(ConfigProblem::new("signature.font_size", "must be greater than 0", Some("e.g. font_size = 20".to_string())));
+    }
+
+    problems
+}
+
+// An optional date of the configuration, empty or YYYY-MM-DD
+fn validate_date(key: &str, value: &str) -> Option<ConfigProblem> {
+    if value.is_empty() || NaiveDate::parse_from_str(value, CONFIG_DATE_FORMAT).is_ok() {
+        return None;
+    }
+
+    let name = key.rsplit('.').next().unwrap_or(key);
+    Some(ConfigProblem::new(key, &format!("{} is not a date", value), Some(format!("use YYYY-MM-DD, e.g. {} = \"2024-09-01\", or leave it empty", name))))
+}
+
+fn validate_base_url(base_url: &str) -> Option<ConfigProblem> {
+    let key = "website.base_url";
+    let example = Some("use the full address, e.g. base_url = \"https://lernplattform.gfn.de\"".to_string());
+
+    let url = match Url::parse(base_url) {
+        Ok(url) => url,
+        Err(e) => return Some(ConfigProblem::new(key, &format!("is not a valid URL ({})", e), example)),
+    };
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Some(ConfigProblem::new(key, &format!("must use http or https, not {}", url.scheme()), example));
+    }
+
+    // The paths are appended to the base URL, a trailing slash would double it
+    if base_url.ends_with('/') {
+        return Some(ConfigProblem::new(key, "must not end with a slash", Some(format!("base_url = \"{}\"", base_url.trim_end_matches('/')))));
+    }
+
+    None
+}
+
+// Turns a deserialize error into a problem, unknown keys get the closest known key as a suggestion
+pub fn deserialize_problem(error: &serde_path_to_error::Error<TomlError>) -> ConfigProblem {
+    let message = error.inner().message().to_string();
+    let mut key = error.path().to_string();
+
+    // The names in backticks: the unknown or missing field first, then the expected ones
+    let names: Vec<&str> = message.split('`').skip(1).step_by(2).collect();
+
+    let suggestion = if message.starts_with("unknown field") {
+        names.split_first().and_then(|(unknown, expected)| closest_name(unknown, expected)).map(|name| format!("did you mean `{}`?", name))
+    } else if message.starts_with("missing field") {
+        if let Some(missing) = names.first() {
+            key = if key == "." { missing.to_string() } else { format!("{}.{}", key, missing) };
+        }
+        Some("add the key, see the README for its meaning".to_string())
+    } else {
+        None
+    };
+
+    ConfigProblem { key, message, suggestion }
+}
+
+// Formats the problems with the file and line of their key, or the environment variable that set it
+pub fn format_problems(problems: &[ConfigProblem], path: &Path, contents: &str, overrides: &[(String, String)]) -> String {
+    let mut lines = vec![format!("The configuration has {} problem(s):", problems.len())];
+
+    for problem in problems {
+        let location = match overrides.iter().find(|(key, _)| *key == problem.key) {
+            Some((_, name)) => format!("environment variable {}", name),
+            None => match find_line(contents, &problem.key) {
+                Some(line) => format!("{}:{}", path.display(), line),
+                None => path.display().to_string(),
+            },
+        };
+
+        lines.push(format!("  {}: {}: {}", location, problem.key, problem.message));
+        if let Some(suggestion) = &problem.suggestion {
+            lines.push(format!("    hint: {}", suggestion));
+        }
+    }
+
+    lines.join("\n")
+}
+
+// The line of a dotted key in the file (1-based), the line of its section if the key itself is missing
+fn find_line(contents: &str, key: &str) -> Option<usize> {
+    // Array indices like "hours.breaks[0].minutes" point to the array itself
+    let key = key.split('[').next().unwrap_or(key);
+    let (section, leaf) = key.rsplit_once('.').unwrap_or(("", key));
+
+    let mut current_section = "";
+    let mut section_line = None;
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if line.starts_with('[') {
+            current_section = line.trim_matches(|c| c == '[' || c == ']').trim();
+            if current_section == section || current_section == key {
+                section_line = section_line.or(Some(index + 1));
+            }
+            continue;
+        }
+
+        if current_section == section {
+            if let Some((name, _)) = line.split_once('=') {
+                if name.trim().trim_matches('"') == leaf {
+                    return Some(index + 1);
+                }
+            }
+        }
+    }
+
+    section_line
+}
+
+fn closest_name<'a>(unknown: &str, expected: &[&'a str]) -> Option<&'a str> {
+    expected.iter()
+        .map(|name| (levenshtein(unknown, name), *name))
+        .filter(|(distance, name)| *distance <= name.len().max(3) / 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml::{Table, Value};
+
+    const CONTENTS: &str = "[account]\nuser_name = \"max\"\n\n[hours]\n# daily = 8\ndaily = 8\nbreaks = [{ after = 6, minutes = 30 }]\n";
+
+    fn problem_of(edit: impl FnOnce(&mut Table)) -> ConfigProblem {
+        let mut table: Table = toml::from_str(include_str!("../../config.toml")).unwrap();
+        edit(&mut table);
+
+        let error = serde_path_to_error::deserialize::<_, GlobalConfig>(Value::Table(table)).err().expect("the configuration should not deserialize");
+        deserialize_problem(&error)
+    }
+
+    #[test]
+    fn measures_the_edit_distance() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("token", ""), 5);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("user_nmae", "user_name"), 2);
+        assert_eq!(levenshtein("bäcker", "backer"), 1);
+    }
+
+    #[test]
+    fn finds_the_line_of_a_key() {
+        assert_eq!(find_line(CONTENTS, "account.user_name"), Some(2));
+        assert_eq!(find_line(CONTENTS, "hours.daily"), Some(6));
+        assert_eq!(find_line(CONTENTS, "hours.breaks[0].minutes"), Some(7));
+        // A missing key points to its section, an unknown section to nothing
+        assert_eq!(find_line(CONTENTS, "account.password"), Some(1));
+        assert_eq!(find_line(CONTENTS, "hours"), Some(4));
+        assert_eq!(find_line(CONTENTS, "vault.path"), None);
+    }
+
+    #[test]
+    fn suggests_the_closest_key_for_an_unknown_one() {
+        let problem = problem_of(|table| {
+            let account = table.get_mut("account").and_then(Value::as_table_mut).unwrap();
+            let user_name = account.remove("user_name").unwrap();
+            account.insert("user_nmae".to_string(), user_name);
+        });
+
+        assert_eq!(problem.key, "account.user_nmae");
+        assert!(problem.message.starts_with("unknown field `user_nmae`"), "{}", problem.message);
+        assert_eq!(problem.suggestion.as_deref(), Some("did you mean `user_name`?"));
+    }
+
+    #[test]
+    fn names_the_missing_key() {
+        let problem = problem_of(|table| {
+            table.get_mut("account").and_then(Value::as_table_mut).unwrap().remove("user_name");
+        });
+        assert_eq!(problem.key, "account.user_name");
+
+        let problem = problem_of(|table| {
+            table.remove("website");
+        });
+        assert_eq!(problem.key, "website");
+    }
+
+    #[test]
+    fn rejects_an_invalid_cutoff_date() {
+        let config_with = |cutoff_date: &str| {
+            let mut table: Table = toml::from_str(include_str!("../../config.toml")).unwrap();
+            table.get_mut("attendance").and_then(Value::as_table_mut).unwrap().insert("cutoff_date".to_string(), Value::String(cutoff_date.to_string()));
+            Value::Table(table).try_into::<GlobalConfig>().unwrap()
+        };
+        let cutoff_problems = |config: &GlobalConfig| validate_config(config).into_iter().filter(|problem| problem.key == "attendance.cutoff_date").count();
+
+        assert_eq!(cutoff_problems(&config_with("")), 0);
+        assert_eq!(cutoff_problems(&config_with("2024-09-01")), 0);
+        assert_eq!(cutoff_problems(&config_with("01.09.2024")), 1);
+        assert_eq!(cutoff_problems(&config_with("2024-02-30")), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_training_dates() {
+        let config_with = |start_date: &str, end_date: &str| {
+            let mut table: Table = toml::from_str(include_str!("../../config.toml")).unwrap();
+            let training = table.entry("training").or_insert_with(|| Value::Table(Table::new())).as_table_mut().unwrap();
+            training.insert("start_date".to_string(), Value::String(start_date.to_string()));
+            training.insert("end_date".to_string(), Value::String(end_date.to_string()));
+            Value::Table(table).try_into::<GlobalConfig>().unwrap()
+        };
+        let training_problems = |config: &GlobalConfig| -> Vec<String> {
+            validate_config(config).into_iter().map(|problem| problem.key).filter(|key| key.starts_with("training.")).collect()
+        };
+
+        assert!(training_problems(&config_with("", "")).is_empty());
+        assert!(training_problems(&config_with("2024-09-01", "")).is_empty());
+        assert!(training_problems(&config_with("2024-09-01", "2026-08-31")).is_empty());
+        assert_eq!(training_problems(&config_with("01.09.2024", "2026-08-31")), ["training.start_date"]);
+        assert_eq!(training_problems(&config_with("", "2026-02-30")), ["training.end_date"]);
+        assert_eq!(training_problems(&config_with("2026-08-31", "2024-09-01")), ["training.end_date"]);
+    }
+
+    #[test]
+    fn reports_the_key_of_a_wrong_type() {
+        let problem = problem_of(|table| {
+            table.get_mut("store").and_then(Value::as_table_mut).unwrap().insert("refresh_days".to_string(), Value::String("7".to_string()));
+        });
+
+        assert_eq!(problem.key, "store.refresh_days");
+        assert_eq!(problem.suggestion, None);
+    }
+}
@@ -0,0 +1,186 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use chrono::NaiveDate;
+use toml::Value;
+use crate::auth::vault::write_private;
+use crate::utils::config::{check_config, CONFIG_DATE_FORMAT};
+
+const BACKENDS: [&str; 2] = ["scrape", "webservice"];
+
+// Commented starter configuration, the placeholders are filled with the answers of the wizard
+const CONFIG_TEMPLATE: &str = r#"# Configuration of the moodle-report-portfolio scraper, see the README for all options
+# Every key can be overridden with an environment variable, e.g. MRP_ACCOUNT__PASSWORD
+
+[account]
+# Your Lernplattform login
+user_name = {user_name}
+# Keep the password out of this file with one of the sources, without any it is asked for at the login
+# password = ""
+# password_env = "GFN_PASSWORD"
+# password_command = "pass show lernplattform"
+# password_file = "/home/you/.lernplattform-password"
+
+[company]
+# The name of your personal educator and your training location
+educator_name = {educator_name}
+location = {location}
+
+[signature]
+# The signature below every week and its font
+signature = {signature}
+font_name = "Pacifico"
+font_size = 20
+
+[website]
+# The address of the Lernplattform, without a trailing slash
+base_url = {base_url}
+# "scrape" parses the rendered pages, "webservice" uses the Moodle REST API and needs a token
+backend = {backend}
+token = {token}
+
+[options]
+# Only scrape the first course
+test_mode = false
+
+[training]
+# The training period as YYYY-MM-DD, every week of it gets a sheet
+start_date = {start_date}
+end_date = {end_date}
+
+[calendar]
+# Mark the public holidays, state is e.g. "NW" for the regional ones
+holidays = true
+{state}
+"#;
+
+// Asks for the essential values and writes a commented starter configuration
+pub fn run_init(path: &Path, force: bool) -> Result<PathBuf, Box<dyn Error>> {
+    if path.exists() && !force {
+        return Err(format!("{} already exists, use --force to overwrite it", path.display()).into());
+    }
+
+    println!("Creating {}, press enter to keep the value in brackets", path.display());
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    let user_name = prompt(&mut input, "Lernplattform user name", "")?;
+    let base_url = prompt(&mut input, "Lernplattform address", "https://lernplattform.gfn.de")?;
+    let backend = prompt_choice(&mut input, "Backend (scrape or webservice)", &BACKENDS, "scrape")?;
+    let token = if backend == "webservice" { prompt(&mut input, "Web service token", "")? } else { String::new() };
+    let educator_name = prompt(&mut input, "Name of your educator", "")?;
+    let location = prompt(&mut input, "Training location", "")?;
+    let signature = prompt(&mut input, "Signature", &user_name)?;
+    let start_date = prompt_date(&mut input, "Start of the training (YYYY-MM-DD, optional)", None)?;
+    let end_date = prompt_date(&mut input, "End of the training (YYYY-MM-DD, optional)", start_date)?;
+    let state = prompt(&mut input, "Federal state for the holidays (e.g. NW, optional)", "")?;
+
+    let state = if state.is_empty() { "# state = \"NW\"".to_string() } else { format!("state = {}", quote(&state.to_uppercase())) };
+
+    let contents = CONFIG_TEMPLATE
+        .replace("{user_name}", &quote(&user_name))
+        .replace("{educator_name}", &quote(&educator_name))
+        .replace("{location}", &quote(&location))
+        .replace("{signature}", &quote(&signature))
+        .replace("{base_url}", &quote(base_url.trim_end_matches('/')))
+        .replace("{backend}", &quote(&backend))
+        .replace("{token}", &quote(&token))
+        .replace("{start_date}", &quote(&format_date(start_date)))
+        .replace("{end_date}", &quote(&format_date(end_date)))
+        .replace("{state}", &state);
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    // Only readable by the user, it may contain the token
+    write_private(path, contents.as_bytes())?;
+
+    // The answers are kept, the problems point to the lines to fix
+    check_config(path, &contents).map_err(|e| format!("Wrote {}, but it is not valid yet\n{}", path.display(), e))?;
+
+    Ok(path.to_path_buf())
+}
+
+// Asks again until the answer is one of the choices
+fn prompt_choice(input: &mut impl BufRead, question: &str, choices: &[&str], default: &str) -> Result<String, Box<dyn Error>> {
+    loop {
+        let answer = prompt(input, question, default)?;
+        if choices.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+
+        println!("{} is not one of {}", answer, choices.join(", "));
+    }
+}
+
+// Asks again until the answer is empty or a date in the format of the configuration, not before `not_before`
+fn prompt_date(input: &mut impl BufRead, question: &str, not_before: Option<NaiveDate>) -> Result<Option<NaiveDate>, Box<dyn Error>> {
+    loop {
+        let answer = prompt(input, question, "")?;
+        if answer.is_empty() {
+            return Ok(None);
+        }
+
+        match NaiveDate::parse_from_str(&answer, CONFIG_DATE_FORMAT) {
+            Ok(date) if not_before.map_or(true, |not_before| date >= not_before) => return Ok(Some(date)),
+            Ok(_) => println!("{} is before the start of the training", answer),
+            Err(e) => println!("{} is not a date as YYYY-MM-DD: {}", answer, e),
+        }
+    }
+}
+
+fn format_date(date: Option<NaiveDate>) -> String {
+    date.map(|date| date.format(CONFIG_DATE_FORMAT).to_string()).unwrap_or_default()
+}
+
+fn prompt(input: &mut impl BufRead, question: &str, default: &str) -> Result<String, Box<dyn Error>> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default.to_string() } else { answer.to_string() })
+}
+
+// A TOML string literal with all special characters escaped
+fn quote(value: &str) -> String {
+    Value::String(value.to_string()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asks_again_for_an_unknown_backend() {
+        let mut input = io::Cursor::new("rest\nwebservice\n");
+        assert_eq!(prompt_choice(&mut input, "Backend", &BACKENDS, "scrape").unwrap(), "webservice");
+
+        let mut input = io::Cursor::new("\n");
+        assert_eq!(prompt_choice(&mut input, "Backend", &BACKENDS, "scrape").unwrap(), "scrape");
+    }
+
+    #[test]
+    fn asks_again_for_an_invalid_date() {
+        let start = NaiveDate::from_ymd_opt(2024, 9, 1);
+
+        let mut input = io::Cursor::new("01.09.2024\n2024-02-30\n2024-09-01\n");
+        assert_eq!(prompt_date(&mut input, "Start", None).unwrap(), start);
+
+        let mut input = io::Cursor::new("2024-08-31\n2026-08-31\n");
+        assert_eq!(prompt_date(&mut input, "End", start).unwrap(), NaiveDate::from_ymd_opt(2026, 8, 31));
+
+        // The dates are optional
+        let mut input = io::Cursor::new("\n");
+        assert_eq!(prompt_date(&mut input, "Start", None).unwrap(), None);
+        assert_eq!(format_date(None), "");
+    }
+}
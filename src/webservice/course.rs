@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::Arc;
+use chrono::{Datelike, Local, TimeZone};
+use futures::{stream, StreamExt};
+use log::{debug, error, info, warn};
+use reqwest::Client;
+use crate::auth::retry::is_auth_error;
+use crate::class::classbook::{extract_description_activities, limit_time_range};
+use crate::class::course::parse_course;
+use crate::common::parse::{ParseError, ENTRY_DATE_FORMAT, TIME_FORMAT};
+use crate::common::tables::{Attendance, AttendanceSession, AttendanceStatus, AttendanceSessionStatus, Classbook, ClassbookEntry, Course, CourseModule, CourseSection, EnrolledCourse, SiteInfo, TimeRange};
+use crate::webservice::rest::call_function;
+use crate::CONFIG;
+
+const CLASSBOOK_NAME: &str = "Klassenbuch";
+const ATTENDANCE_MODULE: &str = "attendance";
+
+// Fetches all courses with their classbooks and the attendance of the current user through the web services
+// A failing course is reported and skipped, its error is returned with the fetched courses, a rejected token fails the whole fetch
+pub async fn fetch_courses_and_attendance(client: Arc<Client>, settled: &HashSet<String>) -> Result<(Vec<Course>, Vec<Attendance>, Vec<String>), Box<dyn Error>> {
+    let site_info: SiteInfo = call_function(&client, "core_webservice_get_site_info", &[]).await?;
+    let enrolled_courses: Vec<EnrolledCourse> = call_function(&client, "core_enrol_get_users_courses", &[("userid", site_info.userid.to_string())]).await?;
+
+    let mut courses: Vec<Course> = enrolled_courses.iter()
+        .filter_map(|course| parse_course(&course.id.to_string(), &course.fullname))
+        .collect();
+
+    if CONFIG.get_test_mode() {
+        info!("Running in test mode, only fetching the first course");
+        courses.truncate(1); // Only fetch the first course in test mode
+    }
+
+    // Settled courses are taken from the local store
+    courses.retain(|course| !settled.contains(&course.id));
+    info!("Fetching {} courses", courses.len());
+
+    let max_concurrent_tasks = CONFIG.get_max_in_flight().max(1); // The request rate itself is limited in get_body
+    let temp_results: Vec<Result<(Course, Vec<Attendance>), (String, Box<dyn Error>)>> = stream::iter(courses.into_iter().map(|course| {
+        let client = Arc::clone(&client);
+
+        async move {
+            let (classbooks, failed_classbooks, attendances) = match fetch_classbooks(&client, &course.id, site_info.userid).await {
+                Ok(result) => result,
+                Err(e) => {
+                    error!("Failed to fetch the classbooks of course {}: {}", course.id, e);
+                    return Err((course.id, e));
+                }
+            };
+            let mut course = course;
+            course.classbooks = classbooks;
+            course.failed_classbooks = failed_classbooks;
+
+            Ok((course, attendances))
+        }
+    })).buffer_unordered(max_concurrent_tasks).collect().await;
+
+    let mut courses = Vec::new();
+    let mut attendances = Vec::new();
+    let mut failures = Vec::new();
+    for result in temp_results {
+        match result {
+            Ok((course, course_attendances)) => {
+                courses.push(course);
+                attendances.extend(course_attendances);
+            }
+            Err((_, e)) if is_auth_error(&*e) => return Err(e),
+            Err((course_id, e)) => failures.push(format!("course {}: {}", course_id, e)),
+        }
+    }
+
+    Ok((courses, attendances, failures))
+}
+
+// Fetches all classbooks of a course (in course order) with the attendance records of the given user, together with the IDs of the failed classbooks
+// A failing classbook is reported and skipped, the course only fails if none of its classbooks could be fetched
+async fn fetch_classbooks(client: &Client, course_id: &str, user_id: u64) -> Result<(Vec<Classbook>, Vec<String>, Vec<Attendance>), Box<dyn Error>> {
+    let sections: Vec<CourseSection> = call_function(client, "core_course_get_contents", &[("courseid", course_id.to_string())]).await?;
+
+    let modules: Vec<&CourseModule> = sections.iter()
+        .flat_map(|section| section.modules.iter())
+        .filter(|module| module.modname == ATTENDANCE_MODULE && module.name.trim() == CLASSBOOK_NAME)
+        .collect();
+
+    if modules.is_empty() {
+        return Err(format!("no classbook found in course {}", course_id).into());
+    }
+
+    let mut classbooks = Vec::new();
+    let mut attendances = Vec::new();
+    let mut failed = Vec::new();
+    let mut failures = Vec::new();
+
+    for module in modules {
+        match fetch_classbook(client, module, user_id).await {
+            Ok((classbook, classbook_attendances)) => {
+                classbooks.push(classbook);
+                attendances.extend(classbook_attendances);
+            }
+            Err(e) if is_auth_error(&*e) => return Err(e),
+            Err(e) => {
+                error!("Failed to fetch classbook {} of course {}: {}", module.id, course_id, e);
+                failures.push(format!("classbook {}: {}", module.id, e));
+                failed.push(module.id.to_string());
+            }
+        }
+    }
+
+    if classbooks.is_empty() {
+        return Err(format!("no classbook processed ({})", failures.join(", ")).into());
+    }
+
+    Ok((classbooks, failed, attendances))
+}
+
+// Fetches the sessions of a classbook and the attendance records of the given user
+async fn fetch_classbook(client: &Client, module: &CourseModule, user_id: u64) -> Result<(Classbook, Vec<Attendance>), Box<dyn Error>> {
+    let sessions: Vec<AttendanceSession> = call_function(client, "mod_attendance_get_sessions", &[("attendanceid", module.instance.to_string())]).await?;
+
+    let mut entries = Vec::new();
+    let mut attendances = Vec::new();
+    for session in &sessions {
+        let start = Local.timestamp_opt(session.sessdate, 0).single().ok_or("invalid session date")?;
+        let end = Local.timestamp_opt(session.sessdate + session.duration, 0).single().ok_or("invalid session duration")?;
+
+        let raw_time = format!("{} - {}", start.format(TIME_FORMAT), end.format(TIME_FORMAT));
+        let time = match TimeRange::parse(&raw_time) {
+            Ok(time) => time,
+            Err(e) => {
+                warn!("Skipping classbook session: {}", ParseError::new(e, &raw_time, &module.url));
+                continue;
+            }
+        };
+
+        let (description, activities) = extract_description_activities(&session.description).await?;
+
+        let entry = ClassbookEntry {
+            weekday: start.weekday(),
+            date: start.date_naive(),
+            time: Some(limit_time_range(Some(time))),
+            description,
+            activities,
+            hours: None,
+            learning_field: None,
+        };
+
+        debug!("Session -> Weekday: {}, Date: {}, Time: {}", entry.weekday, entry.date.format(ENTRY_DATE_FORMAT), time);
+        entries.push(entry);
+
+        // Sessions that have not been taken yet have no log for the user
+        if let Some(log) = session.attendance_log.iter().find(|log| log.studentid == user_id) {
+            let status = session.statuses.iter()
+                .find(|status| status.id == log.statusid)
+                .map_or(AttendanceStatus::Present, session_status);
+
+            // Absences have no times, just like on the attendance page
+            attendances.push(Attendance {
+                date: start.date_naive(),
+                time: if status.is_absence() { None } else { Some(time) },
+                status,
+                remarks: log.remarks.trim().to_string(),
+                duration: if status.is_absence() { None } else { Some((session.duration / 60) as u32) },
+            });
+        }
+    }
+
+    let classbook = Classbook {
+        id: module.id.to_string(),
+        link: module.url.clone(),
+        direct_link: Some(format!("{}&view=5", module.url)),
+        entries,
+    };
+
+    Ok((classbook, attendances))
+}
+
+// The status of the attendance module, taken from its description or acronym, unknown statuses count as present
+fn session_status(status: &AttendanceSessionStatus) -> AttendanceStatus {
+    AttendanceStatus::parse(&status.description)
+        .or_else(|| AttendanceStatus::parse(&status.acronym))
+        .unwrap_or(AttendanceStatus::Present)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::{init, mock, MockResponse};
+
+    fn function(name: &str, params: &str) -> String {
+        format!("wsfunction={}&moodlewsrestformat=json{}", name, params)
+    }
+
+    #[tokio::test]
+    async fn fetches_the_courses_and_collects_the_failed_ones() {
+        init();
+        mock(&function("core_webservice_get_site_info", ""), vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/site_info.json"))]);
+        mock(&function("core_enrol_get_users_courses", "&userid=7"), vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/users_courses.json"))]);
+        mock(&function("core_course_get_contents", "&courseid=201"), vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/course_contents.json"))]);
+        mock(&function("core_course_get_contents", "&courseid=202"), vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/invalid_parameter.json"))]);
+        mock(&function("mod_attendance_get_sessions", "&attendanceid=21"), vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/sessions.json"))]);
+        mock(&function("mod_attendance_get_sessions", "&attendanceid=22"), vec![MockResponse::status(500)]);
+
+        let (courses, attendances, failures) = fetch_courses_and_attendance(Arc::new(Client::new()), &HashSet::new()).await.unwrap();
+
+        // "Info Kurs" is not a Lernfeld, course 202 failed
+        assert_eq!(courses.len(), 1);
+        let course = &courses[0];
+        assert_eq!((course.id.as_str(), course.course.as_str(), course.name.as_str()), ("201", "LF08", "Daten systemübergreifend bereitstellen"));
+        assert_eq!(course.classbooks.len(), 1);
+        assert_eq!(course.classbooks[0].id, "1001");
+        assert_eq!(course.classbooks[0].entries.len(), 3);
+        assert!(course.classbooks[0].entries[0].description.starts_with("Einführung in SQL"));
+        assert_eq!(course.failed_classbooks, vec!["1002".to_string()]);
+
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("course 202: "), "{}", failures[0]);
+        assert!(failures[0].contains("invalidparameter"), "{}", failures[0]);
+
+        // The session without a log has not been taken yet, the log of another student is ignored
+        let statuses: Vec<AttendanceStatus> = attendances.iter().map(|attendance| attendance.status).collect();
+        assert_eq!(statuses, vec![AttendanceStatus::Present, AttendanceStatus::Excused]);
+        assert!(attendances[0].time.is_some());
+        assert_eq!(attendances[1].time, None);
+        assert_eq!(attendances[1].remarks, "Arzttermin");
+    }
+
+    #[tokio::test]
+    async fn skips_settled_courses() {
+        init();
+        mock(&function("core_webservice_get_site_info", ""), vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/site_info.json"))]);
+        mock(&function("core_enrol_get_users_courses", "&userid=7"), vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/users_courses.json"))]);
+
+        let settled: HashSet<String> = ["201", "202"].iter().map(|id| id.to_string()).collect();
+        let (courses, attendances, failures) = fetch_courses_and_attendance(Arc::new(Client::new()), &settled).await.unwrap();
+
+        assert!(courses.is_empty());
+        assert!(attendances.is_empty());
+        assert!(failures.is_empty());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod course;
+pub mod rest;
\ No newline at end of file
@@ -0,0 +1,83 @@
+use std::error::Error;
+use reqwest::{Client, Url};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::auth::client::get_body;
+use crate::auth::fixtures::is_replaying;
+use crate::auth::retry::RequestError;
+use crate::CONFIG;
+
+const REST_PATH: &str = "/webservice/rest/server.php";
+
+// Error codes of a missing, invalid or expired token and of a token without access to the function
+const AUTH_ERROR_CODES: [&str; 3] = ["invalidtoken", "accessexception", "webservice_access_exception"];
+
+// Calls a Moodle web service function and deserializes its JSON response
+pub async fn call_function<T: DeserializeOwned>(client: &Client, function: &str, params: &[(&str, String)]) -> Result<T, Box<dyn Error>> {
+    if CONFIG.get_token().is_empty() && !is_replaying() {
+        return Err(RequestError::Auth { reason: "No web service token configured in [website] token".to_string() }.into());
+    }
+
+    let mut query = vec![
+        ("wstoken", CONFIG.get_token().to_string()),
+        ("wsfunction", function.to_string()),
+        ("moodlewsrestformat", "json".to_string()),
+    ];
+    query.extend(params.iter().cloned());
+
+    let url = Url::parse_with_params(&format!("{}{}", CONFIG.get_base_url(), REST_PATH), &query)?;
+    let body = get_body(client, url.as_str()).await?;
+    let value: Value = serde_json::from_str(&body)?;
+
+    // Moodle reports errors with a regular 200 response containing an exception object
+    if let Some(exception) = value.get("exception").and_then(Value::as_str) {
+        let message = value.get("message").and_then(Value::as_str).unwrap_or_default();
+        let error_code = value.get("errorcode").and_then(Value::as_str).unwrap_or_default();
+
+        let reason = format!("Web service function {} failed: {} ({}, {})", function, message, exception, error_code);
+        if AUTH_ERROR_CODES.contains(&error_code) || AUTH_ERROR_CODES.contains(&exception) {
+            return Err(RequestError::Auth { reason }.into());
+        }
+
+        return Err(reason.into());
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::retry::is_auth_error;
+    use crate::common::tables::SiteInfo;
+    use crate::utils::testing::{init, mock, MockResponse, TEST_TOKEN};
+
+    #[tokio::test]
+    async fn reports_the_exception_of_a_function() {
+        init();
+        mock("wsfunction=core_user_get_users_by_field", vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/invalid_token.json"))]);
+
+        let error = call_function::<SiteInfo>(&Client::new(), "core_user_get_users_by_field", &[]).await.err().unwrap().to_string();
+
+        assert!(error.contains("core_user_get_users_by_field"), "{}", error);
+        assert!(error.contains("invalidtoken"), "{}", error);
+
+        let error = call_function::<SiteInfo>(&Client::new(), "core_user_get_users_by_field", &[]).await.err().unwrap();
+        assert!(is_auth_error(&*error));
+
+        mock("wsfunction=core_user_get_course_user_profiles", vec![MockResponse::json(include_str!("../../tests/fixtures/webservice/invalid_parameter.json"))]);
+        let error = call_function::<SiteInfo>(&Client::new(), "core_user_get_course_user_profiles", &[]).await.err().unwrap();
+        assert!(!is_auth_error(&*error));
+    }
+
+    #[tokio::test]
+    async fn sends_the_token_and_the_parameters() {
+        init();
+        let pattern = format!("wstoken={}&wsfunction=core_calendar_get_calendar_events&moodlewsrestformat=json&courseid=301", TEST_TOKEN);
+        mock(&pattern, vec![MockResponse::json(r#"{"userid": 42}"#)]);
+
+        let site_info: SiteInfo = call_function(&Client::new(), "core_calendar_get_calendar_events", &[("courseid", "301".to_string())]).await.unwrap();
+
+        assert_eq!(site_info.userid, 42);
+    }
+}